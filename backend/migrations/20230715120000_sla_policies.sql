@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS sla_policies (
+    SevCat SMALLINT PRIMARY KEY CHECK (SevCat >= 1 AND SevCat <= 5),
+    FirstResponseTarget INTERVAL NOT NULL,
+    ResolutionTarget INTERVAL NOT NULL
+);
+
+INSERT INTO sla_policies (SevCat, FirstResponseTarget, ResolutionTarget) VALUES
+    (1, INTERVAL '15 minutes', INTERVAL '4 hours'),
+    (2, INTERVAL '1 hour', INTERVAL '1 day'),
+    (3, INTERVAL '4 hours', INTERVAL '3 days'),
+    (4, INTERVAL '8 hours', INTERVAL '5 days'),
+    (5, INTERVAL '1 day', INTERVAL '14 days')
+ON CONFLICT (SevCat) DO NOTHING;
+
+-- elapsed times are measured against the current time for issues that haven't been responded to/resolved yet
+CREATE OR REPLACE VIEW issue_sla AS
+SELECT
+    issues.Id,
+    issues.SevCat,
+    issues.Created,
+    issues.Resolved,
+    issues.FirstResponseTimedate IS NULL AS AwaitingFirstResponse,
+    COALESCE(issues.FirstResponseTimedate, CURRENT_TIMESTAMP) - issues.Created > sla_policies.FirstResponseTarget AS FirstResponseBreached,
+    GREATEST(COALESCE(issues.FirstResponseTimedate, CURRENT_TIMESTAMP) - issues.Created - sla_policies.FirstResponseTarget, INTERVAL '0') AS FirstResponseBreachDuration,
+    CASE WHEN issues.Resolved THEN COALESCE(issues.ResolvedTimedate, CURRENT_TIMESTAMP) ELSE CURRENT_TIMESTAMP END - issues.Created > sla_policies.ResolutionTarget AS ResolutionBreached,
+    GREATEST(CASE WHEN issues.Resolved THEN COALESCE(issues.ResolvedTimedate, CURRENT_TIMESTAMP) ELSE CURRENT_TIMESTAMP END - issues.Created - sla_policies.ResolutionTarget, INTERVAL '0') AS ResolutionBreachDuration
+FROM issues
+JOIN sla_policies ON sla_policies.SevCat = issues.SevCat;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS severity_rules (
+    Id SERIAL PRIMARY KEY,
+    Kind VARCHAR NOT NULL CHECK (Kind IN ('tag', 'keyword')),
+    Pattern VARCHAR NOT NULL,
+    SevCat SMALLINT NOT NULL CHECK (SevCat >= 1 AND SevCat <= 5),
+    UNIQUE (Kind, Pattern)
+);
+
+-- 'default' until either a severity rule matches ('auto') or a staff member sets it with /set_severity ('manual')
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS SevCatSource VARCHAR NOT NULL DEFAULT 'default'
+    CHECK (SevCatSource IN ('default', 'auto', 'manual'));
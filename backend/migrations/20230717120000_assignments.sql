@@ -0,0 +1,13 @@
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS AssignedTo VARCHAR;
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS AssignedToId VARCHAR;
+
+CREATE TABLE IF NOT EXISTS assignment_history (
+    Id SERIAL PRIMARY KEY,
+    IssueId INTEGER NOT NULL REFERENCES issues (Id) ON DELETE CASCADE,
+    AssignedTo VARCHAR NOT NULL,
+    AssignedToId VARCHAR NOT NULL,
+    AssignedBy VARCHAR NOT NULL,
+    AssignedTimedate TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS issues_assignedtoid_idx ON issues (AssignedToId);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS staff_roster (
+    DiscordUserId VARCHAR PRIMARY KEY,
+    Username VARCHAR NOT NULL,
+    OnDuty BOOLEAN NOT NULL DEFAULT false,
+    LastAssignedTimedate TIMESTAMP WITH TIME ZONE,
+    LastUpdated TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1 @@
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS ElevatedTimedate TIMESTAMP WITH TIME ZONE;
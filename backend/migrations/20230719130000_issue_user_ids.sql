@@ -0,0 +1,9 @@
+-- Discord user IDs next to the usernames, since usernames can change. NULL for GitHub issues and for threads
+-- recorded before this until /refresh or the reconciler reads their messages again
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS OriginalPosterId VARCHAR;
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS FirstResponseUserId VARCHAR;
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS ResolverUserId VARCHAR;
+
+CREATE INDEX IF NOT EXISTS issues_original_poster_id_idx ON issues (OriginalPosterId);
+CREATE INDEX IF NOT EXISTS issues_first_response_user_id_idx ON issues (FirstResponseUserId);
+CREATE INDEX IF NOT EXISTS issues_resolver_user_id_idx ON issues (ResolverUserId);
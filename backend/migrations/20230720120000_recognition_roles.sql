@@ -0,0 +1,20 @@
+-- a user qualifies for a role if they meet any of its (non-null) thresholds within the rolling window
+CREATE TABLE IF NOT EXISTS recognition_roles (
+    RoleId VARCHAR PRIMARY KEY,
+    MinResolvedThreads INTEGER CHECK (MinResolvedThreads > 0),
+    MinAcceptedSolutions INTEGER CHECK (MinAcceptedSolutions > 0),
+    WindowDays INTEGER NOT NULL DEFAULT 30 CHECK (WindowDays > 0)
+);
+
+CREATE TABLE IF NOT EXISTS recognition_role_changes (
+    Id SERIAL PRIMARY KEY,
+    DiscordUserId VARCHAR NOT NULL,
+    Username VARCHAR NOT NULL,
+    RoleId VARCHAR NOT NULL,
+    Action VARCHAR NOT NULL CHECK (Action IN ('granted', 'revoked')),
+    ResolvedThreads BIGINT NOT NULL,
+    AcceptedSolutions BIGINT NOT NULL,
+    Timedate TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS recognition_role_changes_user_role_idx ON recognition_role_changes (DiscordUserId, RoleId, Timedate DESC);
@@ -0,0 +1,8 @@
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS Helpful BOOLEAN;
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS Rating SMALLINT CHECK (Rating >= 1 AND Rating <= 5);
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS Comment VARCHAR;
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS SubmittedBy VARCHAR;
+-- everyone other than the original poster who took part in the thread when it was resolved
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS Responders TEXT[];
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS Created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS LastUpdated TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,3 @@
+ALTER TABLE feedback ADD COLUMN IF NOT EXISTS PromptMessageId VARCHAR;
+
+CREATE UNIQUE INDEX IF NOT EXISTS feedback_prompt_message_id ON feedback (PromptMessageId);
@@ -0,0 +1,6 @@
+-- overrides for the defaults in settings.rs, keyed by field name
+CREATE TABLE IF NOT EXISTS settings (
+    Key VARCHAR PRIMARY KEY,
+    Value JSONB NOT NULL,
+    LastUpdated TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
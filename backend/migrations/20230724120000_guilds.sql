@@ -0,0 +1,36 @@
+-- per-guild configuration. Settings holds overrides on top of the global settings, keyed by field name,
+-- and DashboardUsers holds the GitHub logins allowed to view the guild on the dashboard
+CREATE TABLE IF NOT EXISTS guilds (
+    GuildId VARCHAR PRIMARY KEY,
+    Name VARCHAR NOT NULL,
+    StaffRoleId VARCHAR NOT NULL,
+    HelpForumIds VARCHAR[] NOT NULL DEFAULT '{}',
+    GithubRepo VARCHAR,
+    Settings JSONB NOT NULL DEFAULT '{}',
+    DashboardUsers VARCHAR[] NOT NULL DEFAULT '{}',
+    Created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS GuildId VARCHAR;
+
+-- thread links look like https://discord.com/channels/<guild id>/<thread id>
+UPDATE issues SET GuildId = split_part(DiscordThreadLink, '/', 5)
+WHERE GuildId IS NULL AND DiscordThreadLink LIKE 'https://%discord.com/channels/%';
+
+CREATE INDEX IF NOT EXISTS issues_guild_idx ON issues (GuildId);
+
+-- new columns can only be added to the end of a view
+CREATE OR REPLACE VIEW issue_sla AS
+SELECT
+    issues.Id,
+    issues.SevCat,
+    issues.Created,
+    issues.Resolved,
+    issues.FirstResponseTimedate IS NULL AS AwaitingFirstResponse,
+    COALESCE(issues.FirstResponseTimedate, CURRENT_TIMESTAMP) - issues.Created > sla_policies.FirstResponseTarget AS FirstResponseBreached,
+    GREATEST(COALESCE(issues.FirstResponseTimedate, CURRENT_TIMESTAMP) - issues.Created - sla_policies.FirstResponseTarget, INTERVAL '0') AS FirstResponseBreachDuration,
+    CASE WHEN issues.Resolved THEN COALESCE(issues.ResolvedTimedate, CURRENT_TIMESTAMP) ELSE CURRENT_TIMESTAMP END - issues.Created > sla_policies.ResolutionTarget AS ResolutionBreached,
+    GREATEST(CASE WHEN issues.Resolved THEN COALESCE(issues.ResolvedTimedate, CURRENT_TIMESTAMP) ELSE CURRENT_TIMESTAMP END - issues.Created - sla_policies.ResolutionTarget, INTERVAL '0') AS ResolutionBreachDuration,
+    issues.GuildId
+FROM issues
+JOIN sla_policies ON sla_policies.SevCat = issues.SevCat;
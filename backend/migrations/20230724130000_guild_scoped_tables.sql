@@ -0,0 +1,42 @@
+-- severity rules, SLA targets, the on-duty roster and recognition roles are set up per guild.
+-- Rows from before guilds existed are given to the guild from the secrets on startup
+ALTER TABLE guilds ADD COLUMN IF NOT EXISTS StaffChannelId VARCHAR;
+
+ALTER TABLE severity_rules ADD COLUMN IF NOT EXISTS GuildId VARCHAR;
+ALTER TABLE severity_rules DROP CONSTRAINT IF EXISTS severity_rules_kind_pattern_key;
+CREATE UNIQUE INDEX IF NOT EXISTS severity_rules_guild_kind_pattern_idx ON severity_rules (GuildId, Kind, Pattern);
+
+ALTER TABLE staff_roster ADD COLUMN IF NOT EXISTS GuildId VARCHAR;
+ALTER TABLE staff_roster DROP CONSTRAINT IF EXISTS staff_roster_pkey;
+CREATE UNIQUE INDEX IF NOT EXISTS staff_roster_guild_user_idx ON staff_roster (GuildId, DiscordUserId);
+
+ALTER TABLE recognition_roles ADD COLUMN IF NOT EXISTS GuildId VARCHAR;
+ALTER TABLE recognition_role_changes ADD COLUMN IF NOT EXISTS GuildId VARCHAR;
+
+-- policies without a guild are the defaults for guilds that haven't set their own
+ALTER TABLE sla_policies ADD COLUMN IF NOT EXISTS GuildId VARCHAR;
+ALTER TABLE sla_policies DROP CONSTRAINT IF EXISTS sla_policies_pkey;
+CREATE UNIQUE INDEX IF NOT EXISTS sla_policies_guild_sevcat_idx ON sla_policies (GuildId, SevCat);
+CREATE UNIQUE INDEX IF NOT EXISTS sla_policies_default_sevcat_idx ON sla_policies (SevCat) WHERE GuildId IS NULL;
+
+CREATE OR REPLACE VIEW issue_sla AS
+SELECT
+    issues.Id,
+    issues.SevCat,
+    issues.Created,
+    issues.Resolved,
+    issues.FirstResponseTimedate IS NULL AS AwaitingFirstResponse,
+    COALESCE(issues.FirstResponseTimedate, CURRENT_TIMESTAMP) - issues.Created > sla_policies.FirstResponseTarget AS FirstResponseBreached,
+    GREATEST(COALESCE(issues.FirstResponseTimedate, CURRENT_TIMESTAMP) - issues.Created - sla_policies.FirstResponseTarget, INTERVAL '0') AS FirstResponseBreachDuration,
+    CASE WHEN issues.Resolved THEN COALESCE(issues.ResolvedTimedate, CURRENT_TIMESTAMP) ELSE CURRENT_TIMESTAMP END - issues.Created > sla_policies.ResolutionTarget AS ResolutionBreached,
+    GREATEST(CASE WHEN issues.Resolved THEN COALESCE(issues.ResolvedTimedate, CURRENT_TIMESTAMP) ELSE CURRENT_TIMESTAMP END - issues.Created - sla_policies.ResolutionTarget, INTERVAL '0') AS ResolutionBreachDuration,
+    issues.GuildId
+FROM issues
+-- the guild's own policy if it has one, otherwise the default
+JOIN LATERAL (
+    SELECT FirstResponseTarget, ResolutionTarget FROM sla_policies
+    WHERE sla_policies.SevCat = issues.SevCat
+    AND (sla_policies.GuildId = issues.GuildId OR sla_policies.GuildId IS NULL)
+    ORDER BY sla_policies.GuildId IS NULL
+    LIMIT 1
+) sla_policies ON TRUE;
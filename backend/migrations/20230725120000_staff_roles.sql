@@ -0,0 +1,14 @@
+-- staff roles per guild. A member's tier is the highest tier of any of their roles
+CREATE TABLE IF NOT EXISTS staff_roles (
+    GuildId VARCHAR NOT NULL REFERENCES guilds (GuildId) ON DELETE CASCADE,
+    RoleId VARCHAR NOT NULL,
+    Tier VARCHAR NOT NULL CHECK (Tier IN ('helper', 'moderator', 'maintainer')),
+    PRIMARY KEY (GuildId, RoleId)
+);
+
+-- the single staff role could use every command, so it carries over as a maintainer role
+INSERT INTO staff_roles (GuildId, RoleId, Tier)
+SELECT GuildId, StaffRoleId, 'maintainer' FROM guilds
+ON CONFLICT (GuildId, RoleId) DO NOTHING;
+
+ALTER TABLE guilds DROP COLUMN IF EXISTS StaffRoleId;
@@ -0,0 +1,9 @@
+-- how far an interrupted /refresh got through each source, so the next run can pick up from there.
+-- Source is forum:<forum id> (Cursor is the archive timestamp to page back from) or github:<owner/repo> (Cursor is the next page)
+CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+    GuildId VARCHAR NOT NULL REFERENCES guilds (GuildId) ON DELETE CASCADE,
+    Source VARCHAR NOT NULL,
+    Cursor VARCHAR NOT NULL,
+    Updated TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (GuildId, Source)
+);
@@ -0,0 +1,24 @@
+-- long-running work started from Discord, like /refresh backfills, and how far it's got
+CREATE TABLE IF NOT EXISTS jobs (
+    Id SERIAL PRIMARY KEY,
+    Kind VARCHAR NOT NULL,
+    GuildId VARCHAR NOT NULL REFERENCES guilds (GuildId) ON DELETE CASCADE,
+    StartedBy VARCHAR,
+    Status VARCHAR NOT NULL DEFAULT 'running' CHECK (Status IN ('running', 'succeeded', 'failed')),
+    Stage VARCHAR,
+    ThreadsChecked INTEGER NOT NULL DEFAULT 0,
+    IssuesChecked INTEGER NOT NULL DEFAULT 0,
+    RowsCreated INTEGER NOT NULL DEFAULT 0,
+    RowsUpdated INTEGER NOT NULL DEFAULT 0,
+    ThreadsLinked INTEGER NOT NULL DEFAULT 0,
+    ErrorCount INTEGER NOT NULL DEFAULT 0,
+    Errors TEXT[] NOT NULL DEFAULT '{}',
+    Failure VARCHAR,
+    Started TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    Finished TIMESTAMP WITH TIME ZONE
+);
+
+-- only one job of each kind can run in a guild at a time
+CREATE UNIQUE INDEX IF NOT EXISTS jobs_one_running_idx ON jobs (Kind, GuildId) WHERE Status = 'running';
+
+CREATE INDEX IF NOT EXISTS jobs_guild_idx ON jobs (GuildId, Kind, Started DESC);
@@ -0,0 +1,5 @@
+-- whether a help thread is still open on Discord. Kept up to date by the bot, and by the reconciler
+-- for anything that happened while the bot was offline. NULL for issues that didn't come from Discord
+ALTER TABLE issues ADD COLUMN IF NOT EXISTS ThreadState VARCHAR CHECK (ThreadState IN ('active', 'archived', 'deleted'));
+
+UPDATE issues SET ThreadState = 'active' WHERE ThreadState IS NULL AND DiscordThreadId IS NOT NULL;
@@ -0,0 +1,641 @@
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use octocrab::models::issues::Issue as GithubIssue;
+use octocrab::models::IssueState;
+use octocrab::{params, Octocrab};
+use poise::serenity_prelude::{
+    self as serenity, ChannelId, GuildChannel, GuildId, Http, Message, MessageId, ThreadsData,
+    Timestamp,
+};
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::database::{BackfillGithubIssue, BackfillThread, DBQueries, JobProgress, Upserted};
+use crate::github::{Github, RepoRef};
+use crate::guilds::GuildConfig;
+
+/// The `jobs` kind for backfills.
+pub const REFRESH_JOB: &str = "refresh";
+
+/// Only the first few errors are kept, so a systemic failure doesn't flood the status message.
+const MAX_ERRORS: usize = 5;
+
+#[derive(Default)]
+pub struct BackfillProgress {
+    pub threads_checked: usize,
+    pub issues_checked: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub linked: usize,
+    pub error_count: usize,
+    pub errors: Vec<String>,
+}
+
+impl BackfillProgress {
+    fn record(&mut self, upserted: Upserted) {
+        match upserted {
+            Upserted::Created => self.created += 1,
+            Upserted::Updated => self.updated += 1,
+            Upserted::Unchanged => {}
+        }
+    }
+
+    fn job_progress(&self, stage: &str) -> JobProgress {
+        JobProgress {
+            stage: stage.to_string(),
+            threads_checked: self.threads_checked as i32,
+            issues_checked: self.issues_checked as i32,
+            rows_created: self.created as i32,
+            rows_updated: self.updated as i32,
+            threads_linked: self.linked as i32,
+            error_count: self.error_count as i32,
+            errors: self.errors.clone(),
+        }
+    }
+
+    fn record_error(&mut self, e: String) {
+        error!("Error while refreshing: {e}");
+        self.error_count += 1;
+        if self.errors.len() < MAX_ERRORS {
+            self.errors.push(e);
+        }
+    }
+}
+
+impl fmt::Display for BackfillProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checked {} threads and {} GitHub issues: {} added, {} updated, {} linked to elevated issues.",
+            self.threads_checked, self.issues_checked, self.created, self.updated, self.linked
+        )?;
+
+        if self.error_count > 0 {
+            write!(f, "\n{} errors:", self.error_count)?;
+            for e in &self.errors {
+                write!(f, "\n- {e}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Backfills a guild's help threads (active and archived) and GitHub issues into the `issues`
+/// table. Rows are upserted, so it's safe to run again, and the position in each paged source is
+/// checkpointed so an interrupted run picks up where it left off.
+pub struct Backfill {
+    job_id: i32,
+    http: Arc<Http>,
+    db: DBQueries,
+    crab: Github,
+    guild: GuildConfig,
+    status: Message,
+    progress: BackfillProgress,
+    complete_threads: HashSet<String>,
+}
+
+impl Backfill {
+    /// Progress is saved to the job and shown by editing the status message as each page is
+    /// finished.
+    pub fn new(
+        job_id: i32,
+        http: Arc<Http>,
+        db: DBQueries,
+        crab: Github,
+        guild: GuildConfig,
+        status: Message,
+    ) -> Self {
+        Self {
+            job_id,
+            http,
+            db,
+            crab,
+            guild,
+            status,
+            progress: BackfillProgress::default(),
+            complete_threads: HashSet::new(),
+        }
+    }
+
+    pub async fn run(mut self) -> Result<BackfillProgress, anyhow::Error> {
+        let res = self.backfill().await;
+
+        match &res {
+            Ok(()) => self.report("Refresh done!").await,
+            Err(e) => {
+                self.report(&format!(
+                    "Refresh stopped: {e}\nRun `/refresh` again to pick up where it left off."
+                ))
+                .await
+            }
+        };
+
+        let failure = res.as_ref().err().map(|e| e.to_string());
+        if let Err(e) = self.db.clone().finish_job(self.job_id, failure).await {
+            error!("Couldn't mark refresh job {} as finished: {e}", self.job_id);
+        }
+
+        res.map(|_| self.progress)
+    }
+
+    async fn backfill(&mut self) -> Result<(), anyhow::Error> {
+        self.complete_threads = self
+            .db
+            .clone()
+            .backfill_get_complete_threads(self.guild_id())
+            .await?
+            .into_iter()
+            .collect();
+
+        self.report("Checking active threads...").await;
+        let active_threads = self.guild.guild_id.get_active_threads(&self.http).await?;
+        for thread in &active_threads.threads {
+            self.backfill_thread(thread).await;
+        }
+
+        for forum_id in self.guild.help_forum_ids.clone() {
+            self.backfill_archived_threads(forum_id).await?;
+        }
+
+        // threads go first so the elevated issues have rows to be linked to
+        for repo in self.repos() {
+            self.backfill_github_issues(&repo).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn backfill_archived_threads(
+        &mut self,
+        forum_id: ChannelId,
+    ) -> Result<(), anyhow::Error> {
+        let source = format!("forum:{forum_id}");
+        let mut before = self
+            .db
+            .clone()
+            .get_backfill_checkpoint(self.guild_id(), source.clone())
+            .await?
+            .and_then(|cursor| DateTime::parse_from_rfc3339(&cursor).ok())
+            .map(|cursor| cursor.with_timezone(&Utc));
+
+        loop {
+            self.report(&format!("Checking archived threads in <#{forum_id}>..."))
+                .await;
+
+            let page = archived_threads_page(&self.http, forum_id, before).await?;
+            for thread in &page.threads {
+                self.backfill_thread(thread).await;
+            }
+
+            before = next_archived_page(&page, None);
+            let Some(cursor) = before else {
+                break;
+            };
+            self.db
+                .clone()
+                .set_backfill_checkpoint(self.guild_id(), source.clone(), cursor.to_rfc3339())
+                .await?;
+        }
+
+        self.db
+            .clone()
+            .clear_backfill_checkpoint(self.guild_id(), source)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn backfill_thread(&mut self, thread: &GuildChannel) {
+        if !self.guild.watches(thread.parent_id) {
+            return;
+        }
+        self.progress.threads_checked += 1;
+
+        // threads the bot already knows everything about only need their status checked
+        let read_messages = !self.complete_threads.contains(&thread.id.to_string());
+        let record = match thread_record(&self.http, &self.guild, thread, read_messages).await {
+            Ok(res) => res,
+            Err(e) => {
+                self.progress.record_error(format!(
+                    "Couldn't read the messages in <#{}>: {e}",
+                    thread.id
+                ));
+                return;
+            }
+        };
+
+        match self.db.clone().backfill_thread(record).await {
+            Ok(upserted) => self.progress.record(upserted),
+            Err(e) => self
+                .progress
+                .record_error(format!("Couldn't save <#{}>: {e}", thread.id)),
+        }
+    }
+
+    async fn backfill_github_issues(&mut self, repo: &RepoRef) -> Result<(), anyhow::Error> {
+        let source = format!("github:{repo}");
+        let mut page_number = self
+            .db
+            .clone()
+            .get_backfill_checkpoint(self.guild_id(), source.clone())
+            .await?
+            .and_then(|cursor| cursor.parse::<u32>().ok())
+            .unwrap_or(1);
+
+        let crab = self.crab.client_for(repo).await?;
+        let issue_prefix = self.guild.settings.elevated_issue_prefix.clone();
+        let thread_url = thread_url_regex(self.guild.guild_id)?;
+
+        loop {
+            self.report(&format!(
+                "Checking GitHub issues in {repo} (page {page_number})..."
+            ))
+            .await;
+
+            let page = crab
+                .issues(&repo.owner, &repo.name)
+                .list()
+                .state(params::State::All)
+                .per_page(100)
+                .page(page_number)
+                .send()
+                .await;
+            self.db.metrics.record_github_call("list_issues", &page);
+            let page = page?;
+
+            // the issues API lists pull requests too
+            for issue in page
+                .items
+                .iter()
+                .filter(|issue| issue.pull_request.is_none())
+            {
+                self.progress.issues_checked += 1;
+
+                if issue.title.starts_with(&issue_prefix) {
+                    self.link_elevated_issue(issue, &thread_url).await;
+                } else {
+                    self.backfill_github_issue(&crab, repo, issue).await;
+                }
+            }
+
+            if page.next.is_none() {
+                break;
+            }
+            page_number += 1;
+            self.db
+                .clone()
+                .set_backfill_checkpoint(self.guild_id(), source.clone(), page_number.to_string())
+                .await?;
+        }
+
+        self.db
+            .clone()
+            .clear_backfill_checkpoint(self.guild_id(), source)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn link_elevated_issue(&mut self, issue: &GithubIssue, thread_url: &Regex) {
+        let Some(found) = issue
+            .body
+            .as_deref()
+            .and_then(|body| linked_thread_url(thread_url, self.guild.guild_id, body))
+        else {
+            return;
+        };
+
+        match self
+            .db
+            .clone()
+            .backfill_link_elevated_issue(found, issue.html_url.to_string())
+            .await
+        {
+            Ok(true) => self.progress.linked += 1,
+            Ok(false) => {}
+            Err(e) => self
+                .progress
+                .record_error(format!("Couldn't link {}: {e}", issue.html_url)),
+        }
+    }
+
+    async fn backfill_github_issue(
+        &mut self,
+        crab: &Octocrab,
+        repo: &RepoRef,
+        issue: &GithubIssue,
+    ) {
+        let closed = issue.state == IssueState::Closed;
+        let mut record = BackfillGithubIssue {
+            github_link: issue.html_url.to_string(),
+            guild_id: self.guild_id(),
+            original_poster: issue.user.login.clone(),
+            first_response_user: None,
+            first_response_time_date: None,
+            resolver_user: None,
+            resolved: closed,
+            resolved_time_date: if closed { issue.closed_at } else { None },
+            created: issue.created_at,
+        };
+
+        if issue.comments > 0 {
+            let comments = crab
+                .issues(&repo.owner, &repo.name)
+                .list_comments(issue.number)
+                .per_page(100)
+                .send()
+                .await;
+            self.db
+                .metrics
+                .record_github_call("list_comments", &comments);
+            let comments = match comments {
+                Ok(res) => res,
+                Err(e) => {
+                    self.progress.record_error(format!(
+                        "Couldn't get the comments on {}: {e}",
+                        issue.html_url
+                    ));
+                    return;
+                }
+            };
+
+            let first_response = comments
+                .items
+                .iter()
+                .find(|comment| comment.user.login != issue.user.login);
+            record.first_response_user = first_response.map(|comment| comment.user.login.clone());
+            record.first_response_time_date = first_response.map(|comment| comment.created_at);
+
+            // the last comment on a closed issue is taken to be the one that resolved it, which
+            // may be on a later page
+            if closed {
+                let last_page = match comments.last {
+                    Some(_) => crab.get_page(&comments.last).await.ok().flatten(),
+                    None => None,
+                };
+                record.resolver_user = last_page
+                    .as_ref()
+                    .unwrap_or(&comments)
+                    .items
+                    .last()
+                    .map(|comment| comment.user.login.clone());
+            }
+        }
+
+        match self.db.clone().backfill_github_issue(record).await {
+            Ok(upserted) => self.progress.record(upserted),
+            Err(e) => self
+                .progress
+                .record_error(format!("Couldn't save {}: {e}", issue.html_url)),
+        }
+    }
+
+    /// Every repo the guild's forums elevate to.
+    fn repos(&self) -> Vec<RepoRef> {
+        let mut repos = Vec::new();
+        for forum_id in &self.guild.help_forum_ids {
+            let repo = self
+                .crab
+                .repos
+                .repo_for(Some(forum_id.0), self.guild.github_repo.as_ref());
+            if !repos.contains(repo) {
+                repos.push(repo.clone());
+            }
+        }
+        repos
+    }
+
+    fn guild_id(&self) -> String {
+        self.guild.guild_id.to_string()
+    }
+
+    async fn report(&mut self, stage: &str) {
+        if let Err(e) = self
+            .db
+            .clone()
+            .update_job_progress(self.job_id, self.progress.job_progress(stage))
+            .await
+        {
+            error!(
+                "Couldn't save the progress of refresh job {}: {e}",
+                self.job_id
+            );
+        }
+
+        let content = format!("{stage}\n{}", self.progress);
+        if let Err(e) = self.status.edit(&self.http, |m| m.content(content)).await {
+            error!("Couldn't update the refresh status message: {e}");
+        }
+    }
+}
+
+/// The row for a help thread. Reading its messages for the poster and first responder is an extra
+/// request, so it's only done when `read_messages` is set.
+pub async fn thread_record(
+    http: &Http,
+    guild: &GuildConfig,
+    thread: &GuildChannel,
+    read_messages: bool,
+) -> Result<BackfillThread, serenity::Error> {
+    let mut record = BackfillThread {
+        thread_id: thread.id.to_string(),
+        thread_url: format!(
+            "https://discord.com/channels/{}/{}",
+            guild.guild_id, thread.id
+        ),
+        guild_id: guild.guild_id.to_string(),
+        original_poster: None,
+        original_poster_id: None,
+        initial_message: None,
+        first_response_user: None,
+        first_response_user_id: None,
+        first_response_time_date: None,
+        locked: thread
+            .thread_metadata
+            .is_some_and(|metadata| metadata.locked),
+        archived: thread
+            .thread_metadata
+            .is_some_and(|metadata| metadata.archived),
+        created: to_utc(thread.id.created_at()),
+    };
+
+    if !read_messages {
+        return Ok(record);
+    }
+
+    // asking for the messages after the lowest possible ID gets the oldest ones
+    let mut messages = thread
+        .id
+        .messages(http, |message| message.after(MessageId(1)).limit(100))
+        .await?;
+    messages.sort_by_key(|message| message.id);
+
+    if let Some(first_message) = messages.first() {
+        let first_response = messages
+            .iter()
+            .find(|message| message.author.id != first_message.author.id && !message.author.bot);
+
+        record.original_poster = Some(first_message.author.name.clone());
+        record.original_poster_id = Some(first_message.author.id.to_string());
+        record.initial_message = Some(first_message.content.clone());
+        record.first_response_user = first_response.map(|message| message.author.name.clone());
+        record.first_response_user_id = first_response.map(|message| message.author.id.to_string());
+        record.first_response_time_date =
+            first_response.and_then(|message| to_utc(message.timestamp));
+    }
+
+    Ok(record)
+}
+
+/// A page of the forum's archived threads, archived before `before` (or most recently if it isn't
+/// set). Serenity only takes a thread ID for `before`, but Discord pages by archive timestamp.
+pub async fn archived_threads_page(
+    http: &Http,
+    forum_id: ChannelId,
+    before: Option<DateTime<Utc>>,
+) -> Result<ThreadsData, anyhow::Error> {
+    let mut query = vec![("limit", "100".to_string())];
+    if let Some(before) = before {
+        query.push(("before", before.to_rfc3339_opts(SecondsFormat::Millis, true)));
+    }
+
+    let page = reqwest::Client::new()
+        .get(format!(
+            "https://discord.com/api/v10/channels/{forum_id}/threads/archived/public"
+        ))
+        .header("Authorization", &http.token)
+        .query(&query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ThreadsData>()
+        .await?;
+
+    Ok(page)
+}
+
+/// Where the next page of archived threads starts: the oldest archive timestamp on this page.
+/// There's no next page if Discord has no more, or if this page already goes back past `stop_at`.
+pub fn next_archived_page(
+    page: &ThreadsData,
+    stop_at: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    // threads are returned most recently archived first
+    let oldest = page
+        .threads
+        .iter()
+        .filter_map(|thread| thread.thread_metadata?.archive_timestamp)
+        .filter_map(to_utc)
+        .min()?;
+
+    let reached_stop = stop_at.is_some_and(|stop_at| oldest < stop_at);
+    (page.has_more && !reached_stop).then_some(oldest)
+}
+
+/// Matches links to the guild's threads, with or without `www.`, capturing the thread ID.
+fn thread_url_regex(guild_id: GuildId) -> Result<Regex, regex::Error> {
+    Regex::new(&format!(
+        r"https://(?:www\.)?discord\.com/channels/{guild_id}/([0-9]{{17,20}})"
+    ))
+}
+
+/// The first thread linked in an issue body, written the way `thread_record` stores thread URLs.
+fn linked_thread_url(thread_url: &Regex, guild_id: GuildId, body: &str) -> Option<String> {
+    let thread_id = thread_url.captures(body)?.get(1)?;
+    Some(format!(
+        "https://discord.com/channels/{guild_id}/{}",
+        thread_id.as_str()
+    ))
+}
+
+fn to_utc(timestamp: Timestamp) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(timestamp.unix_timestamp(), 0).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId(803236282088161321);
+
+    fn page(archived: &[&str], has_more: bool) -> ThreadsData {
+        let threads = archived
+            .iter()
+            .enumerate()
+            .map(|(i, archive_timestamp)| {
+                serde_json::json!({
+                    "id": (1096467403424813146u64 + i as u64).to_string(),
+                    "guild_id": GUILD.to_string(),
+                    "type": 11,
+                    "name": "help",
+                    "thread_metadata": {
+                        "archived": true,
+                        "archive_timestamp": archive_timestamp,
+                        "auto_archive_duration": 1440,
+                        "locked": false,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        serde_json::from_value(serde_json::json!({
+            "threads": threads,
+            "members": [],
+            "has_more": has_more,
+        }))
+        .unwrap()
+    }
+
+    fn utc(timestamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap().with_timezone(&Utc)
+    }
+
+    fn linked(body: &str) -> Option<String> {
+        linked_thread_url(&thread_url_regex(GUILD).unwrap(), GUILD, body)
+    }
+
+    #[test]
+    fn linked_thread_url_finds_the_thread() {
+        let url = "https://discord.com/channels/803236282088161321/1096467403424813146";
+
+        assert_eq!(linked(&format!("Generated from a [thread]({url})")).as_deref(), Some(url));
+        assert_eq!(
+            linked("See https://www.discord.com/channels/803236282088161321/1096467403424813146")
+                .as_deref(),
+            Some(url)
+        );
+    }
+
+    #[test]
+    fn linked_thread_url_ignores_other_guilds() {
+        assert_eq!(
+            linked("https://discord.com/channels/1000000000000000000/1096467403424813146"),
+            None
+        );
+        assert_eq!(linked("No link here"), None);
+    }
+
+    #[test]
+    fn next_archived_page_starts_from_the_oldest_thread() {
+        let page = page(&["2023-07-20T10:00:00Z", "2023-07-18T09:30:00Z"], true);
+
+        assert_eq!(next_archived_page(&page, None), Some(utc("2023-07-18T09:30:00Z")));
+        assert_eq!(
+            next_archived_page(&page, Some(utc("2023-07-18T00:00:00Z"))),
+            Some(utc("2023-07-18T09:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_archived_page_stops_at_the_cutoff() {
+        let older = page(&["2023-07-20T10:00:00Z", "2023-07-17T23:59:00Z"], true);
+        let last = page(&["2023-07-20T10:00:00Z"], false);
+
+        assert_eq!(next_archived_page(&older, Some(utc("2023-07-18T00:00:00Z"))), None);
+        assert_eq!(next_archived_page(&last, None), None);
+        assert_eq!(next_archived_page(&page(&[], true), None), None);
+    }
+}
@@ -1,20 +1,111 @@
 use crate::{
-    commands::{docs, elevate, refresh, resolve, set_locked, set_severity},
+    commands::{
+        assign, claim, config, docs, elevate, guild, leaderboard, my_threads, off_duty, on_duty,
+        recognition_roles, refresh, refresh_status, register, resolve, set_locked, set_severity,
+        set_sla, severity_rules, staff_roles, stats,
+    },
+    database::{AssignmentStrategy, ThreadState},
+    feedback,
+    github::Github,
+    guilds::Guilds,
+    severity::SeverityRules,
+    settings::{self, Settings, SharedSettings},
     Bot, DBQueries, DiscordBotData,
 };
 use anyhow::Error;
-use octocrab::Octocrab;
-use poise::serenity_prelude::{Context, GatewayIntents};
+use poise::serenity_prelude::{
+    self as serenity, Channel, Context, GatewayIntents, GuildChannel, GuildId, Http, Interaction,
+    Message, UserId,
+};
 use poise::Event;
 use std::collections::HashSet;
-use tracing::{info};
-use sqlx::types::Json;
+use tracing::{error, info};
 
 type EventError = Box<dyn std::error::Error + Send + Sync>;
 
+/// Commands registered globally, so servers the bot hasn't been set up in yet can run `/guild setup`.
+/// Everything else is registered per guild, which takes effect straight away rather than after
+/// Discord's global propagation delay, and keeps staff commands out of other servers.
+const GLOBAL_COMMANDS: [&str; 1] = ["guild"];
+
+fn commands() -> Vec<poise::Command<DiscordBotData, EventError>> {
+    vec![
+        docs(),
+        elevate(),
+        set_locked(),
+        resolve(),
+        set_severity(),
+        set_sla(),
+        severity_rules(),
+        claim(),
+        assign(),
+        my_threads(),
+        on_duty(),
+        off_duty(),
+        stats(),
+        leaderboard(),
+        recognition_roles(),
+        refresh(),
+        refresh_status(),
+        config(),
+        guild(),
+        staff_roles(),
+        register(),
+    ]
+}
+
+fn guild_commands() -> Vec<poise::Command<DiscordBotData, EventError>> {
+    commands()
+        .into_iter()
+        .filter(|command| !GLOBAL_COMMANDS.contains(&command.name.as_str()))
+        .collect()
+}
+
+/// Replaces the global commands, and the commands in each of the guilds.
+/// A guild the bot can't register commands in (e.g. because it's been removed from it) doesn't stop the others.
+pub async fn register_commands(
+    http: impl AsRef<Http>,
+    guild_ids: &[GuildId],
+) -> Result<(), serenity::Error> {
+    let global_commands = commands()
+        .into_iter()
+        .filter(|command| GLOBAL_COMMANDS.contains(&command.name.as_str()))
+        .collect::<Vec<_>>();
+    poise::builtins::register_globally(&http, &global_commands).await?;
+
+    for guild_id in guild_ids {
+        if let Err(e) = register_guild_commands(&http, *guild_id).await {
+            error!("Couldn't register commands in guild {guild_id}: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn register_guild_commands(
+    http: impl AsRef<Http>,
+    guild_id: GuildId,
+) -> Result<(), serenity::Error> {
+    poise::builtins::register_in_guild(http, &guild_commands(), guild_id).await
+}
+
 async fn handle_event(ctx: &Context, event: &Event<'_>, data: &DiscordBotData) -> Result<(), EventError> {
+    data.db
+        .metrics
+        .discord_events
+        .with_label_values(&[event.name()])
+        .inc();
+
     match event {
         Event::ThreadCreate { thread, .. } => {
+            // only threads in the forums a guild has set up are tracked
+            let Some(guild) = data.guilds.get(thread.guild_id).await else {
+                return Ok(());
+            };
+            if !guild.watches(thread.parent_id) {
+                return Ok(());
+            }
+
             let thread_url = {
                 format!(
                     "https://discord.com/channels/{}/{}",
@@ -27,141 +118,300 @@ async fn handle_event(ctx: &Context, event: &Event<'_>, data: &DiscordBotData) -
             if let Err(e) = data
                 .db
                 .clone()
-                .discord_create_issue_record(thread_url, thread.id.to_string(), categories)
+                .discord_create_issue_record(
+                    thread_url.clone(),
+                    thread.id.to_string(),
+                    categories.clone(),
+                    thread.guild_id.to_string(),
+                )
                 .await
             {
                 return Err(format!("Error when creating a new issue record: {e}").into());
             }
+
+            suggest_severity(
+                data,
+                guild.guild_id,
+                &categories,
+                &thread.name,
+                thread_url.clone(),
+            )
+            .await;
+
+            if let Some(strategy) = data.auto_assign_strategy {
+                auto_assign(ctx, data, thread, strategy, &guild.settings, thread_url).await;
+            }
         }
         Event::Message { new_message, .. } => {
+            if new_message.author.bot {
+                return Ok(());
+            }
+
+            let Some(guild_id) = new_message.guild_id else {
+                return Ok(());
+            };
+            let Some(guild) = data.guilds.get(guild_id).await else {
+                return Ok(());
+            };
+
+            // only messages in threads in the forums a guild has set up are tracked
+            let Channel::Guild(channel) = new_message.channel_id.to_channel(ctx).await? else {
+                return Ok(());
+            };
+            if !guild.watches(channel.parent_id) {
+                return Ok(());
+            }
+
             let thread_url = {
                 format!(
                     "https://discord.com/channels/{}/{}",
-                    new_message.guild_id.unwrap(),
-                    new_message.id
+                    guild.guild_id, new_message.channel_id
                 )
             };
 
             let mut messages = new_message
                 .channel_id
-                .messages(ctx.http.clone(), |message| message)
-                .await
-                .unwrap();
+                .messages(&ctx.http, |message| message)
+                .await?
+                .into_iter()
+                .filter(|message| !message.author.bot)
+                .collect::<Vec<Message>>();
             messages.reverse();
 
+            let Some(initial_message) = messages.first() else {
+                return Ok(());
+            };
+
             if messages.len() == 1 {
-                let initial_message = messages.first().unwrap();
-                let (author, contents) = (
+                let (author, author_id, contents) = (
                     initial_message.author.name.to_owned(),
+                    initial_message.author.id.to_string(),
                     initial_message.content.to_owned(),
                 );
 
                 if let Err(e) = data
                     .db
                     .clone()
-                    .discord_update_initial_message(author, contents, thread_url)
+                    .discord_update_initial_message(
+                        author,
+                        author_id,
+                        contents.clone(),
+                        thread_url.clone(),
+                    )
                     .await
                 {
                     return Err(format!("Error when updating initial thread message: {e}").into());
                 }
 
+                suggest_severity(data, guild.guild_id, &[], &contents, thread_url).await;
+
                 return Ok(());
             }
 
-            let mut messages_filtered = messages.clone();
+            let thread_owner = initial_message.author.id;
+
+            let responders = messages
+                .iter()
+                .map(|message| message.author.id)
+                .filter(|id| *id != thread_owner)
+                .collect::<HashSet<UserId>>();
 
-            let thread_owner = messages.first().unwrap().author.clone();
+            let Some(first_response) = messages
+                .iter()
+                .find(|message| message.author.id != thread_owner)
+            else {
+                return Ok(());
+            };
 
-            let mut hsstr = messages
-                .clone()
+            let responses = messages
                 .iter()
-                .map(|x| *x.author.id.as_u64())
-                .collect::<HashSet<u64>>();
-
-            hsstr.remove(thread_owner.id.as_u64());
-
-            let msg_iter = messages.clone().into_iter();
-
-            for res in msg_iter {
-                let message_owner = res.author.name.to_string();
-
-                if message_owner != thread_owner.name {
-                    messages_filtered.retain(|m| m.author.id.to_string() == message_owner);
-
-                    if messages_filtered.len() < 2 && hsstr.len() < 2 {
-                        if let Err(e) = data
-                            .db
-                            .clone()
-                            .discord_get_first_response(&message_owner, thread_url)
-                            .await
-                        {
-                            return Err(
-                                format!("Error when updating initial responder: {e}").into()
-                            );
-                        }
-
-                        info!("Created new initial message for {message_owner}");
-                    }
-                    break;
+                .filter(|message| message.author.id == first_response.author.id)
+                .count();
+
+            if responses < 2 && responders.len() < 2 {
+                let message_owner = &first_response.author.name;
+
+                if let Err(e) = data
+                    .db
+                    .clone()
+                    .discord_get_first_response(
+                        message_owner,
+                        first_response.author.id.to_string(),
+                        thread_url,
+                    )
+                    .await
+                {
+                    return Err(format!("Error when updating initial responder: {e}").into());
                 }
+
+                info!("Created new initial message for {message_owner}");
             }
         }
-        Event::ReactionAdd {add_reaction, ..} => {
-            let message = add_reaction.message(&ctx.http).await.unwrap();
-            if message.author.name == *"shuttlebot"
+        Event::ThreadUpdate { thread } => {
+            let state = if thread.thread_metadata.is_some_and(|metadata| metadata.archived) {
+                ThreadState::Archived
+            } else {
+                ThreadState::Active
+            };
+
+            // threads the bot doesn't track don't have a row, so this does nothing for them
+            if let Err(e) = data
+                .db
+                .clone()
+                .set_thread_state(thread.id.to_string(), state)
+                .await
             {
-            let channel_id = add_reaction.channel_id.to_string();
-            let upvotes = message.reactions[0].count.to_string().parse::<i32>().unwrap() - 1;     
-            let downvotes = message.reactions[1].count.to_string().parse::<i32>().unwrap() - 1;     
+                return Err(format!("Error when updating a thread's state: {e}").into());
+            }
+        }
+        Event::ThreadDelete { thread } => {
+            if let Err(e) = data
+                .db
+                .clone()
+                .set_thread_state(thread.id.to_string(), ThreadState::Deleted)
+                .await
+            {
+                return Err(format!("Error when marking a thread as deleted: {e}").into());
+            }
+        }
+        Event::InteractionCreate { interaction } => match interaction {
+            Interaction::MessageComponent(component) => {
+                feedback::handle_component(ctx, data, component).await?;
+            }
+            Interaction::ModalSubmit(modal) => {
+                feedback::handle_modal(ctx, data, modal).await?;
+            }
+            _ => {}
+        },
+        Event::ReactionAdd { add_reaction, .. } => {
+            feedback::handle_reaction(ctx, data, add_reaction, true).await?;
+        }
+        Event::ReactionRemove { removed_reaction, .. } => {
+            feedback::handle_reaction(ctx, data, removed_reaction, false).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
 
-                data.db.clone().discord_get_feedback(
-                    channel_id,
-                    upvotes,
-                    downvotes
-                ).await.unwrap();
+/// Counts failed commands before handing the error on to poise's default handler.
+async fn on_error(error: poise::FrameworkError<'_, DiscordBotData, EventError>) {
+    if let poise::FrameworkError::Command { ctx, .. } = &error {
+        ctx.data()
+            .db
+            .metrics
+            .command_failures
+            .with_label_values(&[&ctx.command().qualified_name])
+            .inc();
+    }
 
-                }
+    if let Err(e) = poise::builtins::on_error(error).await {
+        error!("Error while handling error: {e}");
     }
-        Event::ReactionRemove {removed_reaction, ..} => {
-            let message = removed_reaction.message(&ctx.http).await.unwrap();
-            if message.author.name == "shuttlebot" {
-                   
-            let channel_id = removed_reaction.channel_id.to_string();
-            let upvotes = message.reactions[0].count.to_string().parse::<i32>().unwrap() - 1;     
-            let downvotes = message.reactions[1].count.to_string().parse::<i32>().unwrap() - 1;     
-
-                data.db.clone().discord_get_feedback(
-                    channel_id,
-                    upvotes,
-                    downvotes
-                ).await.unwrap();
+}
 
-                }
+/// Applies the most severe matching severity rule (if any) to a newly created thread.
+async fn suggest_severity(
+    data: &DiscordBotData,
+    guild_id: GuildId,
+    tags: &[String],
+    text: &str,
+    thread_url: String,
+) {
+    let rules = match data.db.clone().get_severity_rules(guild_id.to_string()).await {
+        Ok(res) => SeverityRules::new(res),
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+
+    if let Some(severity) = rules.suggest(tags, text) {
+        if let Err(e) = data
+            .db
+            .clone()
+            .discord_auto_set_catsev(severity, thread_url)
+            .await
+        {
+            error!("Error when suggesting a severity: {e}");
+        }
     }
-        
-        _ => {}
+}
+
+/// Assigns a new help thread to the next on-duty staff member and tags them in the thread.
+async fn auto_assign(
+    ctx: &Context,
+    data: &DiscordBotData,
+    thread: &GuildChannel,
+    strategy: AssignmentStrategy,
+    settings: &Settings,
+    thread_url: String,
+) {
+    let assignee = match data
+        .db
+        .clone()
+        .pick_on_duty_assignee(thread.guild_id.to_string(), strategy)
+        .await
+    {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            info!("Nobody is on duty, leaving {thread_url} unassigned");
+            return;
+        }
+        Err(e) => {
+            error!("Error when picking an on-duty staff member: {e}");
+            return;
+        }
+    };
+
+    let message = settings::render(
+        &settings.auto_assign_message,
+        &[("assignee", &format!("<@{}>", assignee.id))],
+    );
+
+    match data
+        .db
+        .clone()
+        .discord_assign_thread(assignee, "auto-assignment".to_string(), thread_url)
+        .await
+    {
+        Ok(true) => {
+            if let Err(e) = thread
+                .id
+                .say(&ctx.http, message)
+                .await
+            {
+                error!("Couldn't post auto-assignment message: {e}");
+            }
+        }
+        Ok(false) => error!("Couldn't find an issue record to auto-assign"),
+        Err(e) => error!("Error when auto-assigning thread: {e}"),
     }
-    Ok(())
 }
 
 pub async fn init_discord_bot(
     discord_token: &str,
     db: DBQueries,
-    crab: Octocrab,
-    staff_role_id: String,
-    server_id: String,
+    crab: Github,
+    guilds: Guilds,
+    auto_assign_strategy: Option<AssignmentStrategy>,
+    settings: SharedSettings,
 ) -> Result<Bot, Error> {
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![
-                docs(),
-                elevate(),
-                set_locked(),
-                resolve(),
-                set_severity(),
-                refresh(),
-            ],
+            commands: commands(),
             event_handler: |ctx, event, _, data| Box::pin(handle_event(ctx, event, data)),
+            pre_command: |ctx| {
+                Box::pin(async move {
+                    ctx.data()
+                        .db
+                        .metrics
+                        .command_invocations
+                        .with_label_values(&[&ctx.command().qualified_name])
+                        .inc();
+                })
+            },
+            on_error: |error| Box::pin(on_error(error)),
             ..Default::default()
         })
         .intents(
@@ -172,14 +422,15 @@ pub async fn init_discord_bot(
                 | GatewayIntents::GUILD_MEMBERS
         )
         .token(discord_token)
-        .setup(|ctx, _ready, framework| {
+        .setup(move |ctx, _ready, _framework| {
             Box::pin(async move {
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                register_commands(ctx, &guilds.ids().await).await?;
                 Ok(DiscordBotData {
                     db,
                     crab,
-                    staff_role_id,
-                    server_id,
+                    guilds,
+                    auto_assign_strategy,
+                    settings,
                 })
             })
         });
@@ -1,12 +1,24 @@
+use crate::backfill::{Backfill, REFRESH_JOB};
+use crate::bot::{register_commands, register_guild_commands};
+use crate::digest::WeeklyDigest;
+use crate::feedback;
+use crate::github::RepoRef;
+use crate::guilds::GuildConfig;
+use crate::permissions::{
+    can_manage_bot, can_manage_issues, can_moderate, can_triage, staff_tier,
+    StaffTier,
+};
+use crate::severity::SeverityRules;
+use crate::settings::{self, Settings};
 use crate::utils::Thread;
 use crate::Context;
 use crate::Error;
-use poise::serenity_prelude::{CacheHttp, Error as SerenityError,
+use crate::database::{
+    Assignee, LeaderboardEntry, LeaderboardPeriod, RecognitionRole, SettingOverride,
 };
-use std::collections::HashMap;
+use poise::serenity_prelude::{CacheHttp, Channel, ChannelType, CreateEmbed, Role, User, UserId};
+use std::collections::{HashMap, HashSet};
 use tracing::error;
-use octocrab::models::IssueState;
-use sqlx::types::chrono::Utc;
 
 /// Link to Shuttle documentation
 #[poise::command(slash_command)]
@@ -48,62 +60,83 @@ impl DocsLinks {
     }
 }
 
-async fn check_role(ctx: Context<'_>) -> Result<bool, Error> {
-    let Ok(res) = ctx.author().has_role(
-        ctx.http(),
-        ctx.data().server_id.parse::<u64>().unwrap(),
-        ctx.data().staff_role_id.parse::<u64>().unwrap()
-    ).await else {
-         {error!("Something went wrong trying to check permissions.");
-        return Ok(false)}
-    };
+/// The config for the guild the command was used in, if it's been set up.
+async fn current_guild(ctx: Context<'_>) -> Option<GuildConfig> {
+    ctx.data().guilds.get(ctx.guild_id()?).await
+}
 
-    match res {
-        true => Ok(true),
-        false => Ok(false),
-    }
+/// The settings for the guild the command was used in.
+async fn current_settings(ctx: Context<'_>) -> Settings {
+    ctx.data()
+        .guilds
+        .settings_for(ctx.guild_id(), &ctx.data().settings)
+        .await
 }
 
 /// Elevate a Discord help thread to a GitHub issue. This locks the thread.
-#[poise::command(slash_command, check = "check_role")]
+#[poise::command(
+    slash_command,
+    check = "can_manage_issues",
+    default_member_permissions = "MANAGE_THREADS"
+)]
 pub async fn elevate(ctx: Context<'_>) -> Result<(), Error> {
-    let topic = Thread::get(ctx).await.name;
+    let thread = Thread::get(ctx).await;
+    let topic = thread.name;
+    let guild = current_guild(ctx).await;
+    let repo = ctx
+        .data()
+        .crab
+        .repos
+        .repo_for(
+            thread.parent_id.map(|id| id.0),
+            guild.as_ref().and_then(|guild| guild.github_repo.as_ref()),
+        )
+        .clone();
 
     let thread_url = Thread::url_from_poise_ctx(ctx);
 
     let messages = ctx
         .channel_id()
         .messages(ctx.http(), |message| message)
-        .await
-        .unwrap();
+        .await?;
 
-    let first_message = messages.last().unwrap();
+    let Some(first_message) = messages.last() else {
+        ctx.say("Couldn't find the first message of this thread to elevate.").await?;
+        return Ok(());
+    };
 
     let message_content = first_message.content.clone();
 
-    let issue_title = format!("[AUTOGENERATED] {topic}");
+    let settings = current_settings(ctx).await;
+    let (issue_prefix, elevated_message) =
+        (settings.elevated_issue_prefix, settings.elevated_message);
+
+    let issue_title = format!("{issue_prefix} {topic}");
 
     let message = format!(
         "<sub>Generated from a [discord help thread.]({thread_url})</sub>\n---\n{message_content}"
     );
 
-    match ctx
-        .data()
-        .crab
-        .issues("joshua-mo-143", "test")
+    let crab = match ctx.data().crab.client_for(&repo).await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error when getting a GitHub client: {e}").into()),
+    };
+
+    let res = crab
+        .issues(&repo.owner, &repo.name)
         .create(issue_title)
         .body(message)
         .send()
-        .await
-    {
+        .await;
+    ctx.data().db.metrics.record_github_call("create_issue", &res);
+
+    match res {
         Ok(res) => {
-            ctx.say(format!("This issue was successfully elevated to a GitHub issue! You can find the issue at: {}
-                \n---\n
-                The thread will now be locked to avoid needing to keep both communication channels synced with each other. 
-                \nIf you're not the original poster and you're having the same issue, please open a new Help thread or comment on the GitHub issue.
-                \n---\n
-                Thank you!", &res.html_url))
-                .await?;
+            ctx.say(settings::render(
+                &elevated_message,
+                &[("issue_url", res.html_url.as_str())],
+            ))
+            .await?;
 
             Thread::set_locked_status(ctx, true).await?;
 
@@ -127,7 +160,11 @@ pub async fn elevate(ctx: Context<'_>) -> Result<(), Error> {
 }
 
 /// (un)Lock a thread
-#[poise::command(slash_command, check = "check_role")]
+#[poise::command(
+    slash_command,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
 pub async fn set_locked(
     ctx: Context<'_>,
     #[description = "Set true to lock, set false to unlock"] locked: bool,
@@ -157,65 +194,79 @@ pub async fn set_locked(
     Ok(())
 }
 
-#[poise::command(slash_command)]
+#[poise::command(slash_command, check = "can_triage")]
 pub async fn resolve(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
     
     let thread_url = Thread::url_from_poise_ctx(ctx);
 
-    let member_count: i32 = ctx.channel_id().get_thread_members(&ctx.http()).await.unwrap().len().to_string().parse::<i32>().unwrap() -1;
+    let member_count = ctx.channel_id().get_thread_members(ctx.http()).await?.len() as i32 - 1;
 
-    let messages_len = ctx
-        .channel_id()
-        .messages(ctx.http(), |message| message)
-        .await
-        .unwrap()
-        .len().to_string().parse::<i32>().unwrap();
     let mut messages = ctx
         .channel_id()
         .messages(ctx.http(), |message| message)
-        .await
-        .unwrap();
+        .await?;
+    let messages_len = messages.len() as i32;
     messages.reverse();
 
-    Thread::set_locked_status(ctx, true).await?;
-
-    let thread_author_name = &messages.first().unwrap().author.name;
+    let Some(thread_author_name) = messages.first().map(|message| message.author.name.clone()) else {
+        ctx.say("Couldn't find any messages in this thread to resolve.").await?;
+        return Ok(());
+    };
 
-    let _ = messages
+    let responders = messages
         .iter()
-        .filter(|x| x.author.name == *thread_author_name);
+        .filter(|x| x.author.name != thread_author_name && !x.author.bot)
+        .map(|x| x.author.name.clone())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>();
 
-    let resolved_by = messages
+    let resolver = messages
         .into_iter()
-        .fold(HashMap::<String, usize>::new(), |mut m, x| {
-            *m.entry(x.author.name).or_default() += 1;
+        .fold(HashMap::<UserId, (String, usize)>::new(), |mut m, x| {
+            m.entry(x.author.id).or_insert((x.author.name, 0)).1 += 1;
             m
         })
         .into_iter()
-        .max_by_key(|(_, v)| *v)
-        .map(|(k, _)| k)
-        .unwrap();
+        .max_by_key(|(_, (_, v))| *v)
+        .map(|(id, (name, _))| (id, name));
+    let Some((resolver_id, resolved_by)) = resolver else {
+        ctx.say("Couldn't work out who resolved this thread.").await?;
+        return Ok(());
+    };
+
+    Thread::set_locked_status(ctx, true).await?;
 
     if let Err(e) = ctx
         .data()
         .db
         .clone()
-        .discord_resolve_thread(resolved_by, thread_url, messages_len, member_count)
+        .discord_resolve_thread(
+            resolved_by,
+            resolver_id.to_string(),
+            thread_url,
+            messages_len,
+            member_count,
+        )
         .await
     {
         return Err(format!("Error when resolving thread: {e}").into());
     }
 
-    helpthread_feedback_prompt(&ctx)
-        .await
-        .expect("Error while attempting to get feedback");
-
+    if let Err(e) = feedback::send_prompt(&ctx, responders).await {
+        error!("Error while attempting to get feedback: {e}");
+    }
 
     Ok(())
 }
 
-#[poise::command(slash_command, ephemeral, check = "check_role")]
+#[poise::command(
+    slash_command,
+    ephemeral,
+    check = "can_triage",
+    default_member_permissions = "MANAGE_THREADS"
+)]
 pub async fn set_severity(
     ctx: Context<'_>,
     #[description = "Severity level"] severity: SeverityCategory,
@@ -243,232 +294,1242 @@ pub async fn set_severity(
     Ok(())
 }
 
-#[derive(Debug, poise::ChoiceParameter)]
-pub enum SeverityCategory {
-    #[name = "Category 1 (Shuttle is broken until this issue is fixed)"]
-    One,
-    #[name = "Category 2 (Major inconvenience to many or most Shuttle users)"]
-    Two,
-    #[name = "Category 3 (Minor inconvenience to many or most Shuttle users)"]
-    Three,
-    #[name = "Category 4 (Major inconvenience to one person or total service outage)"]
-    Four,
-    #[name = "Category 5 (Minor inconvenience to one person)"]
-    Five,
-}
+/// Claim this help thread so other staff know you're handling it
+#[poise::command(
+    slash_command,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn claim(ctx: Context<'_>) -> Result<(), Error> {
+    let thread_url = Thread::url_from_poise_ctx(ctx);
 
-impl SeverityCategory {
-    fn to_num(&self) -> i32 {
-        match self {
-            SeverityCategory::One => 1,
-            SeverityCategory::Two => 2,
-            SeverityCategory::Three => 3,
-            SeverityCategory::Four => 4,
-            SeverityCategory::Five => 5,
+    match ctx.data().db.clone().discord_get_assignee(thread_url.clone()).await {
+        Ok(Some(assignee)) if assignee.id == ctx.author().id.to_string() => {
+            ctx.send(|m| m.content("You've already claimed this thread.").ephemeral(true))
+                .await?;
+            return Ok(());
+        }
+        Ok(Some(assignee)) => {
+            ctx.send(|m| {
+                m.content(format!(
+                    "This thread has already been claimed by {}. Use `/assign` if it needs to be handed over.",
+                    assignee.name
+                ))
+                .ephemeral(true)
+            })
+            .await?;
+            return Ok(());
         }
+        Ok(None) => {}
+        Err(e) => return Err(format!("Error when checking thread assignee: {e}").into()),
     }
+
+    assign_thread(ctx, ctx.author(), thread_url).await
+}
+
+/// Assign this help thread to a staff member
+#[poise::command(
+    slash_command,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn assign(
+    ctx: Context<'_>,
+    #[description = "Staff member to assign the thread to"] user: User,
+) -> Result<(), Error> {
+    if staff_tier(ctx, &user).await?.is_none() {
+        ctx.send(|m| {
+            m.content(format!("{} isn't a staff member.", user.name))
+                .ephemeral(true)
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let thread_url = Thread::url_from_poise_ctx(ctx);
+
+    assign_thread(ctx, &user, thread_url).await
 }
 
-#[derive(Clone, Debug)]
-pub struct Issue {
-    discord_thread_id: Option<String>, // unused
-    discord_thread_url: Option<String>,
-    origin: String,
-    original_poster: Option<String>,
-    initial_message: Option<String>,
-    first_response_user: Option<String>,
-    resolver_user: Option<String>,
-    github_link: Option<String>,
-    locked: bool,
-    resolved: bool,
-    first_response_time_date: Option<chrono::DateTime<Utc>>,
-    resolved_time_date: Option<chrono::DateTime<Utc>>
-}
-
-impl Default for Issue {
-    fn default() -> Self {
-        Self {
-            discord_thread_id: None,
-            discord_thread_url: None,
-            origin: "discord".to_string(),
-            initial_message: None,
-            original_poster: None,
-            first_response_user: None,
-            resolver_user: None,
-            github_link: None,
-            locked: false,
-            resolved: false,
-            first_response_time_date: None,
-            resolved_time_date: None,
+async fn assign_thread(ctx: Context<'_>, user: &User, thread_url: String) -> Result<(), Error> {
+    let assignee = Assignee {
+        name: user.name.clone(),
+        id: user.id.to_string(),
+    };
+
+    match ctx
+        .data()
+        .db
+        .clone()
+        .discord_assign_thread(assignee, ctx.author().name.clone(), thread_url)
+        .await
+    {
+        Ok(true) => {
+            ctx.say(format!("This thread has been assigned to <@{}>.", user.id))
+                .await?;
         }
+        Ok(false) => {
+            ctx.send(|m| {
+                m.content("There's no issue record for this thread.")
+                    .ephemeral(true)
+            })
+            .await?;
+        }
+        Err(e) => return Err(format!("Error when assigning thread: {e}").into()),
     }
+
+    Ok(())
 }
 
-#[poise::command(slash_command, check = "check_role")]
-pub async fn refresh(ctx: Context<'_>) -> Result<(), Error> {
-    let _ = ctx.defer().await;
-    let mut issues_list: Vec<Issue> = Vec::new();
+/// List the open help threads assigned to you
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn my_threads(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
 
-    // get github issues
-    let github_issues = ctx
+    let threads = match ctx
         .data()
-        .crab
-        .issues("joshua-mo-143", "test")
-        .list()
-        .send()
-        .await?;
-    let mut github_auto_issues = github_issues.items.clone();
-    let mut github_user_submitted_issues = github_issues.items.clone();
-
-    // filter for autogenerated issues only
-    github_auto_issues.retain(|issue| issue.title.starts_with("[AUTOGENERATED]"));
-    github_user_submitted_issues.retain(|issue| !issue.title.starts_with("[AUTOGENERATED]"));
-    // get all discord channels and only get the ones that are in the Help channel
-    let mut discord_threads = ctx
-        .http()
-        .get_guild(ctx.data().server_id.parse::<u64>().unwrap())
+        .db
+        .clone()
+        .discord_get_assigned_threads(guild_id.to_string(), ctx.author().id.to_string())
         .await
-        .unwrap()
-        .get_active_threads(&ctx.http())
-        .await.unwrap()
-        .threads;
+    {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error when getting assigned threads: {e}").into()),
+    };
 
-    
-    discord_threads.retain(|channel| channel.parent_id == Some(1116377484296978452u64.into()));
-
-    // add messages
-    for thread in discord_threads.clone() {
-        let mut messages = thread
-            .messages(&ctx.http(), |message| message)
-            .await
-            .unwrap();
-
-        messages.reverse();
-
-        let first_response_user = if messages.len() > 1 {Some(messages[1].author.name.clone())} else {None};
-        
-        issues_list.push(Issue {
-            discord_thread_id: Some(thread.id.to_string()),
-            discord_thread_url: Some(format!(
-                "https://www.discord.com/channels/1072109825674203136/{}",
-                thread.id
-            )),
-            original_poster: Some(messages[0].author.name.clone()),
-            first_response_user,
-            ..Default::default()
-        });
-    }
-
-    // parse github issue body for URL; add the github link to the struct
-    for issue in github_auto_issues {
-        let regex =
-            regex::Regex::new(r"https://discord.com/channels/1072109825674203136/[0-9]{19}").unwrap();
-        let body = issue.body.unwrap();
-        let found = regex.find(&body).unwrap().as_str();
-
-        for mut thread in issues_list.clone() {
-            if thread.discord_thread_url == Some(found.to_string()) {
-                thread.github_link = Some(issue.html_url.to_string());
-            }
-        }
+    if threads.is_empty() {
+        ctx.say("You don't have any open threads assigned to you.")
+            .await?;
+        return Ok(());
     }
 
-    for issue in github_user_submitted_issues {
-        println!("{:?}", issue);
-        let comments = match octocrab::instance().issues("joshua-mo-143", "test").list_comments(*issue.id).send().await {
-            Ok(res) => Some(res),
-            Err(_) => None
-        };
-
-        // unwrap ok here as we've already error matched it
-        // typically there will be an error if there are no comments
-        if comments.is_some() {
-        let mut comments = comments.unwrap();
-        let last_comment = if  issue.state == IssueState::Closed {comments.items.pop()} else {None};
-        let first_response_user = if comments.items.len() > 1 {Some(comments.items[1].user.login.clone())} else {None};
-        let first_response_time_date = if comments.items.len() > 1 {Some(comments.items[1].created_at)} else {None};
-            
-        issues_list.push(Issue {
-            origin: "github".to_string(),
-            original_poster: Some(issue.user.login),
-            first_response_user,
-            resolver_user: if issue.state == IssueState::Closed {Some(last_comment.clone().unwrap().user.login)} else {None},
-            github_link: Some(issue.html_url.to_string()),
-            locked: issue.state == IssueState::Closed,
-            resolved: issue.state == IssueState::Closed,
-            first_response_time_date,
-            resolved_time_date: if issue.state == IssueState::Closed {last_comment.unwrap().updated_at} else {None},
-            ..Default::default()
+    let message = threads
+        .iter()
+        .map(|thread| {
+            format!(
+                "- {} (Category {}, opened {})",
+                thread.discord_thread_link, thread.severity, thread.creation_date
+            )
         })
-        } else {
-            
-        issues_list.push(Issue {
-            origin: "github".to_string(),
-            original_poster: Some(issue.user.login),
-            github_link: Some(issue.html_url.to_string()),
-            locked: issue.state == IssueState::Closed,
-            resolved: issue.state == IssueState::Closed,
-            .. Default::default()
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+/// Show a summary of support activity over the last 7 days
+#[poise::command(
+    slash_command,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn stats(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    let digest = WeeklyDigest::fetch(ctx.data().db.clone(), guild.guild_id).await?;
+
+    ctx.send(|m| m.embed(|e| digest.render(e)).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+/// Show the community members who've helped out the most
+#[poise::command(slash_command, guild_only)]
+pub async fn leaderboard(
+    ctx: Context<'_>,
+    #[description = "Time period to rank contributions over (defaults to the last month)"]
+    period: Option<LeaderboardPeriod>,
+    #[description = "Number of users to show (defaults to 10)"]
+    #[min = 1]
+    #[max = 25]
+    top: Option<i64>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let period = period.unwrap_or(LeaderboardPeriod::Month);
+
+    let entries = ctx
+        .data()
+        .db
+        .clone()
+        .get_leaderboard(guild_id.to_string(), period, top.unwrap_or(10))
+        .await?;
+
+    ctx.send(|m| m.embed(|e| render_leaderboard(&entries, period, e)))
+        .await?;
+
+    Ok(())
+}
+
+fn render_leaderboard<'a>(
+    entries: &[LeaderboardEntry],
+    period: LeaderboardPeriod,
+    e: &'a mut CreateEmbed,
+) -> &'a mut CreateEmbed {
+    let description = if entries.is_empty() {
+        "Nobody has helped out in this period yet.".to_string()
+    } else {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(idx, entry)| {
+                format!(
+                    "**{}. {}** - {} resolved, {} accepted, {} first responses, {} 👍",
+                    idx + 1,
+                    entry.name,
+                    entry.resolved_threads,
+                    entry.accepted_solutions,
+                    entry.first_responses,
+                    entry.positive_feedback
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+
+    e.title(format!("Helper leaderboard ({})", period.label()))
+        .description(description)
+        .colour(0xFF8A3F)
+}
+
+/// Manage the roles automatically granted to community members who help out
+#[poise::command(
+    slash_command,
+    subcommands(
+        "set_recognition_role",
+        "remove_recognition_role",
+        "recognition_report"
+    ),
+    check = "can_manage_bot",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn recognition_roles(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Grant a role to anyone who resolves enough threads or has enough accepted solutions
+#[poise::command(slash_command, rename = "set", ephemeral, check = "can_manage_bot")]
+pub async fn set_recognition_role(
+    ctx: Context<'_>,
+    #[description = "Role to grant"] role: Role,
+    #[description = "Resolved threads needed within the window"]
+    #[min = 1]
+    min_resolved_threads: Option<i32>,
+    #[description = "Accepted solutions needed within the window"]
+    #[min = 1]
+    min_accepted_solutions: Option<i32>,
+    #[description = "Size of the rolling window in days (defaults to 30)"]
+    #[min = 1]
+    window_days: Option<i32>,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    if min_resolved_threads.is_none() && min_accepted_solutions.is_none() {
+        ctx.say("Set at least one threshold for this role.").await?;
+        return Ok(());
+    }
+
+    let recognition_role = RecognitionRole {
+        role_id: role.id.to_string(),
+        min_resolved_threads,
+        min_accepted_solutions,
+        window_days: window_days.unwrap_or(30),
+    };
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .set_recognition_role(guild.guild_id.to_string(), recognition_role)
+        .await
+    {
+        return Err(format!("Error when setting recognition role: {e}").into());
+    }
+
+    ctx.say(format!(
+        "{} will now be granted automatically. Roles are synced every hour.",
+        role.name
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Stop granting a recognition role. Members who already have it keep it.
+#[poise::command(slash_command, rename = "remove", ephemeral, check = "can_manage_bot")]
+pub async fn remove_recognition_role(
+    ctx: Context<'_>,
+    #[description = "Role to stop granting"] role: Role,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .db
+        .clone()
+        .remove_recognition_role(guild.guild_id.to_string(), role.id.to_string())
+        .await
+    {
+        Ok(true) => ctx.say(format!("{} is no longer a recognition role.", role.name)).await?,
+        Ok(false) => ctx.say(format!("{} isn't a recognition role.", role.name)).await?,
+        Err(e) => return Err(format!("Error when removing recognition role: {e}").into()),
+    };
+
+    Ok(())
+}
+
+/// Show the most recent recognition role changes
+#[poise::command(slash_command, rename = "report", ephemeral, check = "can_manage_bot")]
+pub async fn recognition_report(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    let changes = ctx
+        .data()
+        .db
+        .clone()
+        .get_recognition_role_changes(20, Some(guild.guild_id.to_string()))
+        .await?;
+
+    if changes.is_empty() {
+        ctx.say("No recognition roles have been granted or revoked yet.")
+            .await?;
+        return Ok(());
+    }
+
+    let message = changes
+        .iter()
+        .map(|change| {
+            format!(
+                "{}: <@&{}> {} {} {} ({} resolved, {} accepted)",
+                change.timedate,
+                change.role_id,
+                change.action,
+                if change.action == "granted" { "to" } else { "from" },
+                change.username,
+                change.resolved_threads,
+                change.accepted_solutions
+            )
         })
-        }        
-    }
-
-    for issue in issues_list {
-        if let Err(e) = sqlx::query(
-            "INSERT INTO issues (
-            origin, 
-            discordthreadid,
-            discordthreadlink,
-            originalposter, 
-            initialmessage, 
-            firstresponseuser, 
-            resolveruser, 
-            githublink, 
-            locked, 
-            resolved, 
-            resolvedtimedate) VALUES ( 
-            $1,
-            $2,
-            $3,
-            $4,
-            $5,
-            $6,
-            $7,
-            $8,
-            $9,
-            $10,
-            $11) ON CONFLICT DO NOTHING"
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+/// Mark yourself as on duty so new help threads can be assigned to you
+#[poise::command(
+    slash_command,
+    ephemeral,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn on_duty(ctx: Context<'_>) -> Result<(), Error> {
+    set_duty_status(ctx, true).await
+}
+
+/// Mark yourself as off duty so new help threads stop being assigned to you
+#[poise::command(
+    slash_command,
+    ephemeral,
+    check = "can_moderate",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn off_duty(ctx: Context<'_>) -> Result<(), Error> {
+    set_duty_status(ctx, false).await
+}
+
+async fn set_duty_status(ctx: Context<'_>, on_duty: bool) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .discord_set_on_duty(
+            guild.guild_id.to_string(),
+            ctx.author().id.to_string(),
+            ctx.author().name.clone(),
+            on_duty,
         )
-        .bind(issue.origin)
-        .bind(issue.discord_thread_id)
-        .bind(issue.discord_thread_url) // original poster
-        .bind(issue.original_poster) // original poster
-        .bind(issue.initial_message) // initial message
-        .bind(issue.first_response_user) // first response user
-        .bind(issue.resolver_user) // resolver user
-        .bind(issue.github_link) // github link
-        .bind(issue.locked) // locked
-        .bind(issue.resolved) // resolved
-        .bind(issue.resolved_time_date) // resolvedtimedate
-        .execute(&ctx.data().db.db)
         .await
-        {
-            ctx.say(format!("Error when refreshing: {e}")).await?;
+    {
+        return Err(format!("Error when updating duty status: {e}").into());
+    }
+
+    let message = if on_duty {
+        "You're now on duty. New help threads may be assigned to you."
+    } else {
+        "You're now off duty."
+    };
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+/// Manage the rules used to suggest a severity for new help threads
+#[poise::command(
+    slash_command,
+    subcommands("add_severity_rule", "remove_severity_rule", "list_severity_rules"),
+    check = "can_manage_bot",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn severity_rules(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Add (or update) a rule that suggests a severity when a forum tag or keyword matches
+#[poise::command(slash_command, rename = "add", ephemeral, check = "can_manage_bot")]
+pub async fn add_severity_rule(
+    ctx: Context<'_>,
+    #[description = "What the rule matches against"] kind: SeverityRuleKind,
+    #[description = "Forum tag ID, or a case-insensitive regex for keyword rules"] pattern: String,
+    #[description = "Severity level to suggest"] severity: SeverityCategory,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    if let SeverityRuleKind::Keyword = kind {
+        if let Err(e) = SeverityRules::build_regex(&pattern) {
+            ctx.say(format!("That isn't a valid regex: {e}")).await?;
+            return Ok(());
         }
     }
 
-    ctx.say("Migrations done!").await?;
+    let id = match ctx
+        .data()
+        .db
+        .clone()
+        .add_severity_rule(
+            guild.guild_id.to_string(),
+            kind.as_str(),
+            pattern,
+            severity.to_num(),
+        )
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error when adding severity rule: {e}").into()),
+    };
+
+    ctx.say(format!("Severity rule {id} saved.")).await?;
 
     Ok(())
 }
 
-pub async fn helpthread_feedback_prompt(ctx: &Context<'_>) -> Result<(), SerenityError> {
-    let message = ctx.channel_id().say(ctx.http(), "Looks like this issue's been resolved! 
-        Feel free to give us feedback on whether this thread helped you by reacting with a thumbs up or down.\n This thread will now be locked.")
-            .await.unwrap();
+/// Remove a severity rule
+#[poise::command(slash_command, rename = "remove", ephemeral, check = "can_manage_bot")]
+pub async fn remove_severity_rule(
+    ctx: Context<'_>,
+    #[description = "ID of the rule to remove"] id: i32,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .db
+        .clone()
+        .remove_severity_rule(guild.guild_id.to_string(), id)
+        .await
+    {
+        Ok(true) => ctx.say(format!("Severity rule {id} removed.")).await?,
+        Ok(false) => ctx.say(format!("There's no severity rule with ID {id}.")).await?,
+        Err(e) => return Err(format!("Error when removing severity rule: {e}").into()),
+    };
+
+    Ok(())
+}
+
+/// List the current severity rules
+#[poise::command(slash_command, rename = "list", ephemeral, check = "can_manage_bot")]
+pub async fn list_severity_rules(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
 
-    message.react(ctx.http(), '👍').await.unwrap();
-    message.react(ctx.http(), '👎').await.unwrap();
+    let rules = ctx
+        .data()
+        .db
+        .clone()
+        .get_severity_rules(guild.guild_id.to_string())
+        .await?;
+
+    if rules.is_empty() {
+        ctx.say("There aren't any severity rules yet.").await?;
+        return Ok(());
+    }
+
+    let message = rules
+        .iter()
+        .map(|rule| {
+            format!(
+                "`{}` {} `{}` -> Category {}",
+                rule.id, rule.kind, rule.pattern, rule.severity
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    ctx.say(message).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum SeverityRuleKind {
+    #[name = "Forum tag"]
+    Tag,
+    #[name = "Keyword (regex)"]
+    Keyword,
+}
+
+impl SeverityRuleKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SeverityRuleKind::Tag => "tag",
+            SeverityRuleKind::Keyword => "keyword",
+        }
+    }
+}
+
+/// Set the first response and resolution targets for a severity category
+#[poise::command(
+    slash_command,
+    ephemeral,
+    check = "can_manage_bot",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn set_sla(
+    ctx: Context<'_>,
+    #[description = "Severity level"] severity: SeverityCategory,
+    #[description = "Target time to first response, in minutes"]
+    #[min = 1]
+    first_response_minutes: i32,
+    #[description = "Target time to resolution, in hours"]
+    #[min = 1]
+    resolution_hours: i32,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .set_sla_policy(
+            guild.guild_id.to_string(),
+            severity.to_num(),
+            first_response_minutes,
+            resolution_hours,
+        )
+        .await
+    {
+        return Err(format!("Error when setting SLA policy: {e}").into());
+    }
+
+    let message = format!(
+        "Category {} issues now need a first response within {first_response_minutes} minutes and a resolution within {resolution_hours} hours.",
+        severity.to_num()
+    );
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum SeverityCategory {
+    #[name = "Category 1 (Shuttle is broken until this issue is fixed)"]
+    One,
+    #[name = "Category 2 (Major inconvenience to many or most Shuttle users)"]
+    Two,
+    #[name = "Category 3 (Minor inconvenience to many or most Shuttle users)"]
+    Three,
+    #[name = "Category 4 (Major inconvenience to one person or total service outage)"]
+    Four,
+    #[name = "Category 5 (Minor inconvenience to one person)"]
+    Five,
+}
+
+impl SeverityCategory {
+    fn to_num(&self) -> i32 {
+        match self {
+            SeverityCategory::One => 1,
+            SeverityCategory::Two => 2,
+            SeverityCategory::Three => 3,
+            SeverityCategory::Four => 4,
+            SeverityCategory::Five => 5,
+        }
+    }
+}
+
+/// Backfill this server's help threads and GitHub issues
+// Runs in the background. Safe to run again, and picks up where it left off if it was interrupted.
+#[poise::command(
+    slash_command,
+    check = "can_manage_issues",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn refresh(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    let job_id = match ctx
+        .data()
+        .db
+        .clone()
+        .start_job(
+            REFRESH_JOB.to_string(),
+            guild.guild_id.to_string(),
+            ctx.author().name.clone(),
+        )
+        .await
+    {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            ctx.say("A refresh is already running - use `/refresh_status` to see how it's going.")
+                .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(format!("Error when starting refresh: {e}").into()),
+    };
+
+    let status = ctx
+        .say(format!("Starting refresh #{job_id}..."))
+        .await?
+        .into_message()
+        .await?;
+
+    let backfill = Backfill::new(
+        job_id,
+        ctx.serenity_context().http.clone(),
+        ctx.data().db.clone(),
+        ctx.data().crab.clone(),
+        guild,
+        status,
+    );
+
+    // runs past the end of the interaction, so large servers don't time out. The status message
+    // and the job say what went wrong
+    tokio::spawn(async move {
+        if let Err(e) = backfill.run().await {
+            error!("Error while refreshing: {e}");
+        }
+    });
+
+    Ok(())
+}
+
+/// See how the latest refresh is going
+#[poise::command(
+    slash_command,
+    ephemeral,
+    check = "can_manage_issues",
+    default_member_permissions = "MANAGE_THREADS"
+)]
+pub async fn refresh_status(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    let job = match ctx
+        .data()
+        .db
+        .clone()
+        .get_latest_job(REFRESH_JOB.to_string(), guild.guild_id.to_string())
+        .await
+    {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            ctx.say("There hasn't been a refresh yet.").await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut message = match &job.finished {
+        Some(finished) => format!(
+            "Refresh #{} {} at {finished} (started at {}).",
+            job.id, job.status, job.started
+        ),
+        None => format!(
+            "Refresh #{} has been running since {}: {}",
+            job.id,
+            job.started,
+            job.stage.as_deref().unwrap_or("starting")
+        ),
+    };
+
+    message.push_str(&format!(
+        "\nChecked {} threads and {} GitHub issues: {} added, {} updated, {} linked to elevated issues.",
+        job.threads_checked, job.issues_checked, job.rows_created, job.rows_updated, job.threads_linked
+    ));
+
+    if let Some(failure) = &job.failure {
+        message.push_str(&format!("\nStopped because: {failure}"));
+    }
+
+    if job.error_count > 0 {
+        message.push_str(&format!("\n{} errors:", job.error_count));
+        for e in &job.errors {
+            message.push_str(&format!("\n- {e}"));
+        }
+    }
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+/// View or change the settings for every server (servers override them with /guild set)
+// owners only, since a server's admins could otherwise change how the bot behaves everywhere else
+#[poise::command(
+    slash_command,
+    subcommands("reload_config", "set_config"),
+    owners_only,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn config(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reload settings from the database
+#[poise::command(slash_command, rename = "reload", ephemeral, owners_only)]
+pub async fn reload_config(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = match Settings::load(ctx.data().db.clone()).await {
+        Ok(res) => res,
+        Err(e) => {
+            ctx.say(format!("Settings weren't reloaded: {e}")).await?;
+            return Ok(());
+        }
+    };
+
+    // guild overrides are layered on top of the global settings, so they need reloading too
+    if let Err(e) = ctx
+        .data()
+        .guilds
+        .reload(ctx.data().db.clone(), &settings)
+        .await
+    {
+        ctx.say(format!("Settings weren't reloaded: {e}")).await?;
+        return Ok(());
+    }
+
+    *ctx.data().settings.write().await = settings;
+
+    ctx.say("Settings reloaded.").await?;
+
+    Ok(())
+}
+
+/// Change a setting. Values are read as JSON where possible, and as text otherwise
+#[poise::command(slash_command, rename = "set", ephemeral, owners_only)]
+pub async fn set_config(
+    ctx: Context<'_>,
+    #[description = "Name of the setting"] key: String,
+    #[description = "New value"] value: String,
+) -> Result<(), Error> {
+    let value = settings::parse_value(&value);
+
+    // check the new value against the rest of the overrides before saving it
+    let mut overrides = match ctx.data().db.clone().get_settings().await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Error when getting settings: {e}").into()),
+    };
+    overrides.retain(|setting| setting.key != key);
+    overrides.push(SettingOverride {
+        key: key.clone(),
+        value: sqlx::types::Json(value.clone()),
+    });
+
+    let settings = match Settings::from_overrides(overrides) {
+        Ok(res) => res,
+        Err(e) => {
+            ctx.say(format!("That setting wasn't saved: {e}")).await?;
+            return Ok(());
+        }
+    };
+
+    if let Err(e) = ctx.data().db.clone().set_setting(key.clone(), value).await {
+        return Err(format!("Error when saving setting: {e}").into());
+    }
+
+    *ctx.data().settings.write().await = settings;
+    reload_guilds(ctx).await?;
+
+    ctx.say(format!("{key} updated.")).await?;
+
+    Ok(())
+}
+
+async fn reload_guilds(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = ctx.data().settings.read().await.clone();
+
+    if let Err(e) = ctx
+        .data()
+        .guilds
+        .reload(ctx.data().db.clone(), &settings)
+        .await
+    {
+        return Err(format!("Error when reloading guilds: {e}").into());
+    }
+
+    Ok(())
+}
+
+/// Set up this server, or change how it's set up
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands(
+        "setup_guild",
+        "add_guild_forum",
+        "set_guild_staff_channel",
+        "allow_dashboard_user",
+        "set_guild_config"
+    ),
+    required_permissions = "MANAGE_GUILD",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn guild(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Set this server's staff role, help forum and (optionally) GitHub repo
+#[poise::command(
+    slash_command,
+    rename = "setup",
+    guild_only,
+    ephemeral,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn setup_guild(
+    ctx: Context<'_>,
+    #[description = "Role that can use every staff command"] staff_role: Role,
+    #[description = "Forum that help threads are created in"] help_forum: Channel,
+    #[description = "Repository to elevate threads to, as owner/repo"] github_repo: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let help_forum = help_forum.guild().filter(|channel| channel.kind == ChannelType::Forum);
+    let Some(help_forum) = help_forum else {
+        ctx.say("The help forum needs to be a forum channel.").await?;
+        return Ok(());
+    };
+
+    let github_repo = match github_repo.map(|repo| repo.parse::<RepoRef>()).transpose() {
+        Ok(res) => res,
+        Err(e) => {
+            ctx.say(e).await?;
+            return Ok(());
+        }
+    };
+
+    // make sure the bot can actually create issues there before saving it
+    if let Some(repo) = &github_repo {
+        if !ctx.data().crab.repos.allows(repo) {
+            ctx.say(format!(
+                "{repo} isn't one of the repositories this bot elevates to. Ask the bot's owner to add it to GITHUB_GUILD_REPOS."
+            ))
+            .await?;
+            return Ok(());
+        }
+        if let Err(e) = ctx.data().crab.client_for(repo).await {
+            ctx.say(format!("Couldn't access {repo} on GitHub: {e}")).await?;
+            return Ok(());
+        }
+    }
+
+    let name = ctx
+        .guild()
+        .map(|guild| guild.name)
+        .unwrap_or_else(|| guild_id.to_string());
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .discord_setup_guild(
+            guild_id.to_string(),
+            name,
+            staff_role.id.to_string(),
+            help_forum.id.to_string(),
+            github_repo.as_ref().map(RepoRef::to_string),
+        )
+        .await
+    {
+        return Err(format!("Error when setting up guild: {e}").into());
+    }
+
+    reload_guilds(ctx).await?;
+
+    // staff commands are only registered in guilds that have been set up
+    if let Err(e) = register_guild_commands(ctx.serenity_context(), guild_id).await {
+        error!("Couldn't register commands in guild {guild_id}: {e}");
+    }
+
+    let repo = match github_repo {
+        Some(repo) => format!(" Threads will be elevated to {repo}."),
+        None => String::new(),
+    };
+
+    ctx.say(format!(
+        "Set up! {} is a maintainer role and threads in <#{}> will be tracked.{repo}",
+        staff_role.name, help_forum.id
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Track help threads in another forum
+#[poise::command(
+    slash_command,
+    rename = "add_forum",
+    guild_only,
+    ephemeral,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn add_guild_forum(
+    ctx: Context<'_>,
+    #[description = "Forum that help threads are created in"] forum: Channel,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    let forum = forum.guild().filter(|channel| channel.kind == ChannelType::Forum);
+    let Some(forum) = forum else {
+        ctx.say("That isn't a forum channel.").await?;
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .db
+        .clone()
+        .discord_add_guild_forum(guild.guild_id.to_string(), forum.id.to_string())
+        .await
+    {
+        Ok(true) => {
+            reload_guilds(ctx).await?;
+            ctx.say(format!("Threads in <#{}> will now be tracked.", forum.id)).await?;
+        }
+        Ok(false) => {
+            ctx.say(format!("Threads in <#{}> are already tracked.", forum.id)).await?;
+        }
+        Err(e) => return Err(format!("Error when adding forum: {e}").into()),
+    }
+
+    Ok(())
+}
+
+/// Post this server's weekly support digest to a channel
+#[poise::command(
+    slash_command,
+    rename = "staff_channel",
+    guild_only,
+    ephemeral,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_guild_staff_channel(
+    ctx: Context<'_>,
+    #[description = "Channel the weekly digest is posted to"] channel: Channel,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .discord_set_guild_staff_channel(guild.guild_id.to_string(), channel.id().to_string())
+        .await
+    {
+        return Err(format!("Error when setting staff channel: {e}").into());
+    }
+
+    reload_guilds(ctx).await?;
+
+    ctx.say(format!(
+        "The weekly digest will be posted to <#{}>.",
+        channel.id()
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Let a GitHub user view this server on the dashboard
+#[poise::command(
+    slash_command,
+    guild_only,
+    ephemeral,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn allow_dashboard_user(
+    ctx: Context<'_>,
+    #[description = "Their GitHub username"] github_login: String,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .db
+        .clone()
+        .discord_add_guild_dashboard_user(guild.guild_id.to_string(), github_login.clone())
+        .await
+    {
+        Ok(true) => {
+            reload_guilds(ctx).await?;
+            ctx.say(format!("{github_login} can now view this server on the dashboard."))
+                .await?;
+        }
+        Ok(false) => {
+            ctx.say(format!("{github_login} can already view this server on the dashboard."))
+                .await?;
+        }
+        Err(e) => return Err(format!("Error when adding dashboard user: {e}").into()),
+    }
+
+    Ok(())
+}
+
+/// Change a setting for this server only. Values are read the same way as `/config set`
+#[poise::command(
+    slash_command,
+    rename = "set",
+    guild_only,
+    ephemeral,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn set_guild_config(
+    ctx: Context<'_>,
+    #[description = "Name of the setting"] key: String,
+    #[description = "New value"] value: String,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        ctx.say("This server hasn't been set up yet - use `/guild setup` first.").await?;
+        return Ok(());
+    };
+
+    let value = settings::parse_value(&value);
+
+    if let Err(e) = guild.settings.with_overrides([(key.clone(), value.clone())]) {
+        ctx.say(format!("That setting wasn't saved: {e}")).await?;
+        return Ok(());
+    }
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .set_guild_setting(guild.guild_id.to_string(), key.clone(), value)
+        .await
+    {
+        return Err(format!("Error when saving setting: {e}").into());
+    }
+
+    reload_guilds(ctx).await?;
+
+    ctx.say(format!("{key} updated for this server.")).await?;
+
+    Ok(())
+}
+
+/// Manage which roles can use staff commands
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommands("set_staff_role", "remove_staff_role", "list_staff_roles"),
+    check = "can_manage_bot",
+    default_member_permissions = "MANAGE_GUILD"
+)]
+pub async fn staff_roles(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Make a role a staff role, or change its tier
+#[poise::command(
+    slash_command,
+    rename = "set",
+    guild_only,
+    ephemeral,
+    check = "can_manage_bot"
+)]
+pub async fn set_staff_role(
+    ctx: Context<'_>,
+    #[description = "Role to give staff commands to"] role: Role,
+    #[description = "What the role can do"] tier: StaffTier,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        return Ok(());
+    };
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .set_staff_role(
+            guild.guild_id.to_string(),
+            role.id.to_string(),
+            tier.as_str().to_string(),
+        )
+        .await
+    {
+        return Err(format!("Error when setting staff role: {e}").into());
+    }
+
+    reload_guilds(ctx).await?;
+
+    ctx.say(format!("{} is now a {} role.", role.name, tier.as_str()))
+        .await?;
+
+    Ok(())
+}
+
+/// Stop a role from using staff commands
+#[poise::command(
+    slash_command,
+    rename = "remove",
+    guild_only,
+    ephemeral,
+    check = "can_manage_bot"
+)]
+pub async fn remove_staff_role(
+    ctx: Context<'_>,
+    #[description = "Role to take staff commands from"] role: Role,
+) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        return Ok(());
+    };
+
+    // removing the last maintainer role would leave nobody able to manage staff roles
+    let maintainers = guild
+        .staff_roles
+        .iter()
+        .filter(|(_, tier)| **tier == StaffTier::Maintainer)
+        .map(|(role_id, _)| *role_id)
+        .collect::<Vec<_>>();
+    if maintainers == [role.id] {
+        ctx.say(format!(
+            "{} is the only maintainer role - make another role a maintainer first.",
+            role.name
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    match ctx
+        .data()
+        .db
+        .clone()
+        .remove_staff_role(guild.guild_id.to_string(), role.id.to_string())
+        .await
+    {
+        Ok(true) => {
+            reload_guilds(ctx).await?;
+            ctx.say(format!("{} is no longer a staff role.", role.name)).await?
+        }
+        Ok(false) => ctx.say(format!("{} isn't a staff role.", role.name)).await?,
+        Err(e) => return Err(format!("Error when removing staff role: {e}").into()),
+    };
+
+    Ok(())
+}
+
+/// List the staff roles and their tiers
+#[poise::command(
+    slash_command,
+    rename = "list",
+    guild_only,
+    ephemeral,
+    check = "can_manage_bot"
+)]
+pub async fn list_staff_roles(ctx: Context<'_>) -> Result<(), Error> {
+    let Some(guild) = current_guild(ctx).await else {
+        return Ok(());
+    };
+
+    let mut roles = guild.staff_roles.into_iter().collect::<Vec<_>>();
+    roles.sort_by_key(|(_, tier)| std::cmp::Reverse(*tier));
+
+    let message = roles
+        .iter()
+        .map(|(role_id, tier)| format!("<@&{role_id}>: {}", tier.as_str()))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    ctx.say(message).await?;
+
+    Ok(())
+}
+
+/// Re-sync the bot's slash commands with Discord in every server that's been set up
+#[poise::command(
+    slash_command,
+    owners_only,
+    ephemeral,
+    default_member_permissions = "ADMINISTRATOR"
+)]
+pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_ids = ctx.data().guilds.ids().await;
+
+    if let Err(e) = register_commands(ctx.serenity_context(), &guild_ids).await {
+        return Err(format!("Error when registering commands: {e}").into());
+    }
+
+    ctx.say(format!("Registered commands in {} server(s).", guild_ids.len()))
+        .await?;
+
+    Ok(())
+}
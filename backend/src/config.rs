@@ -0,0 +1,351 @@
+use jsonwebtoken::EncodingKey;
+use poise::serenity_prelude::{ChannelId, GuildId, RoleId};
+use shuttle_secrets::SecretStore;
+use std::fmt;
+use std::str::FromStr;
+use tracing::info;
+
+use crate::database::AssignmentStrategy;
+use crate::github::RepoMapping;
+
+/// Repository that threads are elevated to if `GITHUB_REPO` isn't set.
+const DEFAULT_GITHUB_REPO: &str = "joshua-mo-143/test";
+
+/// Everything the service reads from `Secrets.toml`, checked up front.
+pub struct Config {
+    pub discord_token: String,
+    pub discord_server_id: GuildId,
+    pub discord_server_staff_role_id: RoleId,
+    pub github_auth: GithubAuthConfig,
+    pub github_repos: RepoMapping,
+    pub oauth: Option<OAuthCredentials>,
+    pub auto_assign_strategy: Option<AssignmentStrategy>,
+    pub staff_channel_id: Option<ChannelId>,
+}
+
+pub enum GithubAuthConfig {
+    App { app_id: u64, key: EncodingKey },
+    PersonalToken(String),
+}
+
+#[derive(Clone)]
+pub struct OAuthCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// Every problem found in the config, so they can all be fixed in one go.
+#[derive(Debug)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Found {} problem(s) with Secrets.toml:", self.0.len())?;
+        for error in &self.0 {
+            writeln!(f, "  - {error}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl Config {
+    pub fn from_secrets(secrets: &SecretStore) -> Result<Self, ConfigErrors> {
+        let mut reader = SecretReader {
+            secrets,
+            errors: Vec::new(),
+        };
+
+        let discord_token = reader.required("DISCORD_TOKEN");
+        let discord_server_id = reader
+            .required("DISCORD_SERVER_ID")
+            .and_then(|id| reader.parse::<u64>("DISCORD_SERVER_ID", &id, "a server ID"))
+            .map(GuildId);
+        let discord_server_staff_role_id = reader
+            .required("DISCORD_SERVER_STAFF_ROLE_ID")
+            .and_then(|id| reader.parse::<u64>("DISCORD_SERVER_STAFF_ROLE_ID", &id, "a role ID"))
+            .map(RoleId);
+
+        // if the app ID and PEM key exist, authenticate as an app - otherwise, fall back to a personal token
+        let github_auth = match (
+            reader.optional("GITHUB_APP_ID"),
+            reader.optional("GITHUB_APP_PRIVATE_KEY"),
+        ) {
+            (Some(app_id), Some(key)) => {
+                let app_id = reader.parse::<u64>("GITHUB_APP_ID", &app_id, "an app ID");
+                let key = match EncodingKey::from_rsa_pem(key.as_bytes()) {
+                    Ok(key) => Some(key),
+                    Err(e) => {
+                        reader.error(format!(
+                            "GITHUB_APP_PRIVATE_KEY isn't a valid RSA private key in PEM format: {e}"
+                        ));
+                        None
+                    }
+                };
+
+                app_id
+                    .zip(key)
+                    .map(|(app_id, key)| GithubAuthConfig::App { app_id, key })
+            }
+            (Some(_), None) => {
+                reader.error(
+                    "GITHUB_APP_ID is set, but GITHUB_APP_PRIVATE_KEY is missing".to_string(),
+                );
+                None
+            }
+            (None, Some(_)) => {
+                reader.error(
+                    "GITHUB_APP_PRIVATE_KEY is set, but GITHUB_APP_ID is missing".to_string(),
+                );
+                None
+            }
+            (None, None) => match reader.optional("GITHUB_PERSONAL_TOKEN") {
+                Some(token) => Some(GithubAuthConfig::PersonalToken(token)),
+                None => {
+                    reader.error(
+                        "Either GITHUB_APP_ID and GITHUB_APP_PRIVATE_KEY, or GITHUB_PERSONAL_TOKEN, need to be set"
+                            .to_string(),
+                    );
+                    None
+                }
+            },
+        };
+
+        let github_repos = {
+            let default_repo = reader
+                .optional("GITHUB_REPO")
+                .unwrap_or_else(|| DEFAULT_GITHUB_REPO.to_string());
+            let forum_repos = reader.optional("GITHUB_FORUM_REPOS").unwrap_or_default();
+            let guild_repos = reader.optional("GITHUB_GUILD_REPOS").unwrap_or_default();
+
+            match RepoMapping::parse(&default_repo, &forum_repos, &guild_repos) {
+                Ok(res) => Some(res),
+                Err(e) => {
+                    reader.error(format!("GITHUB_REPO/GITHUB_FORUM_REPOS/GITHUB_GUILD_REPOS: {e}"));
+                    None
+                }
+            }
+        };
+
+        let oauth = match (
+            reader.optional("GITHUB_OAUTH_ID"),
+            reader.optional("GITHUB_OAUTH_SECRET"),
+        ) {
+            (Some(client_id), Some(client_secret)) => Some(OAuthCredentials {
+                client_id,
+                client_secret,
+            }),
+            (None, None) => None,
+            (Some(_), None) => {
+                reader.error(
+                    "GITHUB_OAUTH_ID is set, but GITHUB_OAUTH_SECRET is missing".to_string(),
+                );
+                None
+            }
+            (None, Some(_)) => {
+                reader.error(
+                    "GITHUB_OAUTH_SECRET is set, but GITHUB_OAUTH_ID is missing".to_string(),
+                );
+                None
+            }
+        };
+
+        let auto_assign_strategy = reader
+            .optional("AUTO_ASSIGN_STRATEGY")
+            .and_then(|strategy| {
+                reader.parse::<AssignmentStrategy>(
+                    "AUTO_ASSIGN_STRATEGY",
+                    &strategy,
+                    "round-robin or least-open-threads",
+                )
+            });
+
+        let staff_channel_id = reader
+            .optional("DISCORD_STAFF_CHANNEL_ID")
+            .and_then(|id| reader.parse::<u64>("DISCORD_STAFF_CHANNEL_ID", &id, "a channel ID"))
+            .map(ChannelId);
+
+        if !reader.errors.is_empty() {
+            return Err(ConfigErrors(reader.errors));
+        }
+
+        // every required value has been checked above, so these are all present if there were no errors
+        Ok(Self {
+            discord_token: discord_token.unwrap(),
+            discord_server_id: discord_server_id.unwrap(),
+            discord_server_staff_role_id: discord_server_staff_role_id.unwrap(),
+            github_auth: github_auth.unwrap(),
+            github_repos: github_repos.unwrap(),
+            oauth,
+            auto_assign_strategy,
+            staff_channel_id,
+        })
+    }
+
+    /// Logs which optional features are turned on, so it's obvious from the startup logs what's been configured.
+    pub fn log_summary(&self) {
+        let github_auth = match self.github_auth {
+            GithubAuthConfig::App { app_id, .. } => format!("GitHub app {app_id}"),
+            GithubAuthConfig::PersonalToken(_) => "personal token".to_string(),
+        };
+
+        info!("GitHub auth: {github_auth}");
+        info!(
+            "GitHub repos: {} (default), {} forum mapping(s), {} guild repo(s)",
+            self.github_repos.default_repo,
+            self.github_repos.forum_repos.len(),
+            self.github_repos.guild_repos.len()
+        );
+        info!("GitHub OAuth: {}", enabled(self.oauth.is_some()));
+        info!("GitHub webhooks: disabled (not supported yet)");
+        info!(
+            "Auto-assignment: {}",
+            match self.auto_assign_strategy {
+                Some(AssignmentStrategy::RoundRobin) => "round-robin",
+                Some(AssignmentStrategy::LeastOpenThreads) => "least-open-threads",
+                None => "disabled",
+            }
+        );
+        info!(
+            "Weekly digest: {}",
+            enabled(self.staff_channel_id.is_some())
+        );
+    }
+}
+
+fn enabled(enabled: bool) -> &'static str {
+    if enabled {
+        "enabled"
+    } else {
+        "disabled"
+    }
+}
+
+struct SecretReader<'a> {
+    secrets: &'a SecretStore,
+    errors: Vec<String>,
+}
+
+impl SecretReader<'_> {
+    /// Missing and empty secrets are both treated as not set.
+    fn optional(&self, name: &str) -> Option<String> {
+        self.secrets
+            .get(name)
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    fn required(&mut self, name: &str) -> Option<String> {
+        let value = self.optional(name);
+        if value.is_none() {
+            self.error(format!("{name} is required but isn't set"));
+        }
+
+        value
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, value: &str, expected: &str) -> Option<T> {
+        match value.trim().parse::<T>() {
+            Ok(res) => Some(res),
+            Err(_) => {
+                self.error(format!("{name} should be {expected}, but was {value:?}"));
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, error: String) {
+        self.errors.push(error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn secrets(values: &[(&str, &str)]) -> SecretStore {
+        SecretStore::new(
+            values
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<BTreeMap<String, String>>(),
+        )
+    }
+
+    fn errors(values: &[(&str, &str)]) -> Vec<String> {
+        Config::from_secrets(&secrets(values))
+            .err()
+            .expect("config should be invalid")
+            .0
+    }
+
+    const MINIMAL: [(&str, &str); 4] = [
+        ("DISCORD_TOKEN", "token"),
+        ("DISCORD_SERVER_ID", "1001"),
+        ("DISCORD_SERVER_STAFF_ROLE_ID", "2002"),
+        ("GITHUB_PERSONAL_TOKEN", "ghp_token"),
+    ];
+
+    #[test]
+    fn reads_a_minimal_config() {
+        let config = Config::from_secrets(&secrets(&MINIMAL)).unwrap();
+
+        assert_eq!(config.discord_server_id, GuildId(1001));
+        assert_eq!(config.discord_server_staff_role_id, RoleId(2002));
+        assert!(matches!(config.github_auth, GithubAuthConfig::PersonalToken(_)));
+        assert_eq!(config.github_repos.default_repo.to_string(), DEFAULT_GITHUB_REPO);
+        assert!(config.oauth.is_none());
+        assert!(config.auto_assign_strategy.is_none());
+        assert!(config.staff_channel_id.is_none());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let errors = errors(&[
+            ("DISCORD_SERVER_ID", "not a number"),
+            ("DISCORD_SERVER_STAFF_ROLE_ID", "  "),
+            ("GITHUB_APP_ID", "123"),
+            ("GITHUB_FORUM_REPOS", "1001"),
+            ("GITHUB_OAUTH_ID", "client"),
+            ("AUTO_ASSIGN_STRATEGY", "random"),
+            ("DISCORD_STAFF_CHANNEL_ID", "general"),
+        ]);
+
+        assert_eq!(errors.len(), 8, "{errors:#?}");
+        for name in [
+            "DISCORD_TOKEN",
+            "DISCORD_SERVER_ID",
+            "DISCORD_SERVER_STAFF_ROLE_ID",
+            "GITHUB_APP_PRIVATE_KEY",
+            "GITHUB_FORUM_REPOS",
+            "GITHUB_OAUTH_SECRET",
+            "AUTO_ASSIGN_STRATEGY",
+            "DISCORD_STAFF_CHANNEL_ID",
+        ] {
+            assert!(
+                errors.iter().any(|error| error.contains(name)),
+                "no error mentions {name}: {errors:#?}"
+            );
+        }
+    }
+
+    #[test]
+    fn needs_some_github_auth() {
+        let errors = errors(&MINIMAL[..3]);
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("GITHUB_PERSONAL_TOKEN"));
+    }
+
+    #[test]
+    fn rejects_an_invalid_app_key() {
+        let mut values = MINIMAL[..3].to_vec();
+        values.extend([("GITHUB_APP_ID", "123"), ("GITHUB_APP_PRIVATE_KEY", "not a key")]);
+        let errors = errors(&values);
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("GITHUB_APP_PRIVATE_KEY"));
+    }
+}
@@ -1,19 +1,119 @@
-use serde::Serialize;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::{types::Json, PgPool};
 use tracing::error;
-use serde_json::{value::Value};
+
+use crate::metrics::Metrics;
 
 #[derive(Clone)]
 pub struct DBQueries {
     pub db: PgPool,
+    pub metrics: Metrics,
 }
 
+/// Shared by the issues list and the issues export. `$1` is the optional assignee filter, `$2` the optional guild.
+const ISSUES_QUERY: &str = "SELECT
+        OriginalPoster as original_poster,
+        DiscordThreadLink as discord_thread_link,
+        issues.SevCat as severity, 
+        FirstResponseUser as first_responder,
+        GithubLink as github_link,
+        ResolverUser as resolved_by,
+        AssignedTo as assigned_to,
+        categories,
+        CAST(DATE(issues.created) as varchar) as creation_date,
+        issue_sla.FirstResponseBreached as first_response_breached,
+        CAST(date_trunc('second', NULLIF(issue_sla.FirstResponseBreachDuration, INTERVAL '0')) as varchar) as first_response_breach_duration,
+        issue_sla.ResolutionBreached as resolution_breached,
+        CAST(date_trunc('second', NULLIF(issue_sla.ResolutionBreachDuration, INTERVAL '0')) as varchar) as resolution_breach_duration
+        from issues
+        JOIN issue_sla ON issue_sla.Id = issues.Id
+        WHERE ($1::varchar IS NULL OR AssignedTo = $1)
+        AND ($2::varchar IS NULL OR issues.GuildId = $2)
+        ";
+
+/// Shared by the dashboard and the weekly stats export. `$1` is the number of weeks, `$2` the timezone,
+/// `$3` the optional guild.
+const WEEKLY_STATS_QUERY: &str = "WITH weeks AS (
+            SELECT generate_series(
+                date_trunc('week', CURRENT_TIMESTAMP AT TIME ZONE $2) - ($1 - 1) * INTERVAL '1 week',
+                date_trunc('week', CURRENT_TIMESTAMP AT TIME ZONE $2),
+                INTERVAL '1 week'
+            ) as week_start
+        ),
+        local_issues AS (
+            SELECT *,
+            created AT TIME ZONE $2 as local_created,
+            ResolvedTimedate AT TIME ZONE $2 as local_resolved,
+            FirstResponseTimedate AT TIME ZONE $2 as local_first_response
+            FROM issues
+            WHERE ($3::varchar IS NULL OR GuildId = $3)
+        )
+        SELECT
+        CONCAT(CAST(DATE(weeks.week_start) as varchar), ' - ', CAST(DATE(weeks.week_start) + 6 as varchar)) as date_range,
+        (SELECT COUNT(*) FROM local_issues
+            WHERE local_created >= weeks.week_start AND local_created < weeks.week_start + INTERVAL '1 week') as total_issues,
+        (SELECT COUNT(*) FROM local_issues WHERE GithubLink IS NOT NULL
+            AND local_created >= weeks.week_start AND local_created < weeks.week_start + INTERVAL '1 week') as total_elevated_issues,
+        (SELECT COUNT(*) FROM local_issues WHERE Resolved = TRUE
+            AND local_resolved >= weeks.week_start AND local_resolved < weeks.week_start + INTERVAL '1 week') as total_resolved_issues,
+        (SELECT COUNT(*) FROM local_issues WHERE messagecount > 6 AND usercount >= 2
+            AND local_created >= weeks.week_start AND local_created < weeks.week_start + INTERVAL '1 week') as total_one_touch_threads,
+        (SELECT COUNT(*) FROM local_issues WHERE messagecount > 50
+            AND local_created >= weeks.week_start AND local_created < weeks.week_start + INTERVAL '1 week') as extended_threads,
+        (SELECT CAST(date_trunc('second', AVG(FirstResponseTimedate - created)) as varchar) FROM local_issues
+            WHERE local_created >= weeks.week_start AND local_created < weeks.week_start + INTERVAL '1 week') as average_response_time,
+        best_solver.name as best_solver,
+        best_solver.count as best_solver_count,
+        best_first_responder.name as best_first_responder,
+        best_first_responder.count as best_first_responder_count
+        FROM weeks
+        LEFT JOIN LATERAL (
+            SELECT ResolverUser as name, COUNT(*) as count FROM local_issues
+            WHERE Resolved = TRUE AND ResolverUser IS NOT NULL
+            AND local_resolved >= weeks.week_start AND local_resolved < weeks.week_start + INTERVAL '1 week'
+            GROUP BY ResolverUser
+            ORDER BY count DESC, name ASC
+            LIMIT 1
+        ) best_solver ON TRUE
+        LEFT JOIN LATERAL (
+            SELECT FirstResponseUser as name, COUNT(*) as count FROM local_issues
+            WHERE FirstResponseUser IS NOT NULL
+            AND local_first_response >= weeks.week_start AND local_first_response < weeks.week_start + INTERVAL '1 week'
+            GROUP BY FirstResponseUser
+            ORDER BY count DESC, name ASC
+            LIMIT 1
+        ) best_first_responder ON TRUE
+        ORDER BY weeks.week_start DESC
+        ";
+
+const JOBS_QUERY: &str = "SELECT
+        Id as id,
+        Kind as kind,
+        GuildId as guild_id,
+        StartedBy as started_by,
+        Status as status,
+        Stage as stage,
+        ThreadsChecked as threads_checked,
+        IssuesChecked as issues_checked,
+        RowsCreated as rows_created,
+        RowsUpdated as rows_updated,
+        ThreadsLinked as threads_linked,
+        ErrorCount as error_count,
+        Errors as errors,
+        Failure as failure,
+        CAST(date_trunc('second', Started) as varchar) as started,
+        CAST(date_trunc('second', Finished) as varchar) as finished
+        FROM jobs";
+
 #[derive(Serialize, sqlx::FromRow)]
 pub struct Issue {
     #[serde(rename(serialize = "originalPoster"))]
     pub original_poster: Option<String>,
     #[serde(rename(serialize = "discordThreadLink"))]
-    pub discord_thread_link: String,
+    pub discord_thread_link: Option<String>,
     pub severity: i16,
     #[serde(rename(serialize = "firstResponder"))]
     pub first_responder: Option<String>,
@@ -21,23 +121,170 @@ pub struct Issue {
     pub github_link: Option<String>,
     #[serde(rename(serialize = "resolvedBy"))]
     pub resolved_by: Option<String>,
+    #[serde(rename(serialize = "assignedTo"))]
+    pub assigned_to: Option<String>,
     pub categories: Option<Vec<String>>,
     #[serde(rename(serialize = "creationDate"))]
     pub creation_date: String,
+    #[serde(rename(serialize = "firstResponseBreached"))]
+    pub first_response_breached: bool,
+    #[serde(rename(serialize = "firstResponseBreachDuration"))]
+    pub first_response_breach_duration: Option<String>,
+    #[serde(rename(serialize = "resolutionBreached"))]
+    pub resolution_breached: bool,
+    #[serde(rename(serialize = "resolutionBreachDuration"))]
+    pub resolution_breach_duration: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct IssueFilters {
+    pub assignee: Option<String>,
+    pub guild: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AssignmentStrategy {
+    RoundRobin,
+    LeastOpenThreads,
+}
+
+impl std::str::FromStr for AssignmentStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "least-open-threads" => Ok(Self::LeastOpenThreads),
+            _ => Err(format!(
+                "Unknown assignment strategy '{s}' (expected round-robin or least-open-threads)"
+            )),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct Assignee {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct AssignedThread {
+    pub discord_thread_link: String,
+    pub severity: i16,
+    pub creation_date: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AssigneeStats {
+    pub assignee: String,
+    #[serde(rename(serialize = "openIssues"))]
+    pub open_issues: i64,
+    #[serde(rename(serialize = "resolvedIssues"))]
+    pub resolved_issues: i64,
+    #[serde(rename(serialize = "averageResolutionTime"))]
+    pub average_resolution_time: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SlaBreach {
+    #[serde(rename(serialize = "discordThreadLink"))]
+    pub discord_thread_link: Option<String>,
+    #[serde(rename(serialize = "githubLink"))]
+    pub github_link: Option<String>,
+    pub severity: i16,
+    #[serde(rename(serialize = "creationDate"))]
+    pub creation_date: String,
+    #[serde(rename(serialize = "awaitingFirstResponse"))]
+    pub awaiting_first_response: bool,
+    #[serde(rename(serialize = "firstResponseBreachDuration"))]
+    pub first_response_breach_duration: Option<String>,
+    #[serde(rename(serialize = "resolutionBreachDuration"))]
+    pub resolution_breach_duration: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct DashboardData {
-    #[serde(rename(serialize = "lastFourWeeksStats"))]
-    pub last_four_weeks_stats: Vec<LastFourWeeksStats>,
+    #[serde(rename(serialize = "weeklyStats"))]
+    pub weekly_stats: Vec<WeeklyStats>,
     #[serde(rename(serialize = "issuesAwaitingResponse"))]
     pub issues_awaiting_response: IssuesAwaitingResponse,
     #[serde(rename(serialize = "issuesOpenedLastWeek"))]
     pub issues_opened_last_week: Vec<IssuesOpenedLastWeek>,
+    #[serde(rename(serialize = "slaCompliance"))]
+    pub sla_compliance: Vec<SlaComplianceStats>,
+    #[serde(rename(serialize = "assigneeStats"))]
+    pub assignee_stats: Vec<AssigneeStats>,
+    #[serde(rename(serialize = "responseTimePercentiles"))]
+    pub response_time_percentiles: ResponseTimePercentileBreakdown,
+    pub feedback: FeedbackOverview,
+}
+
+#[derive(Serialize)]
+pub struct FeedbackOverview {
+    pub summary: FeedbackSummary,
+    #[serde(rename(serialize = "recentComments"))]
+    pub recent_comments: Vec<FeedbackComment>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct FeedbackSummary {
+    #[serde(rename(serialize = "totalResponses"))]
+    pub total_responses: i64,
+    pub helpful: i64,
+    #[serde(rename(serialize = "notHelpful"))]
+    pub not_helpful: i64,
+    #[serde(rename(serialize = "averageRating"))]
+    pub average_rating: Option<f64>,
 }
 
 #[derive(Serialize, sqlx::FromRow)]
-pub struct LastFourWeeksStats {
+pub struct FeedbackComment {
+    #[serde(rename(serialize = "discordThreadLink"))]
+    pub discord_thread_link: Option<String>,
+    pub helpful: Option<bool>,
+    pub rating: Option<i16>,
+    pub comment: Option<String>,
+    pub responders: Option<Vec<String>>,
+    #[serde(rename(serialize = "submittedBy"))]
+    pub submitted_by: Option<String>,
+    pub date: String,
+}
+
+#[derive(Serialize)]
+pub struct ResponseTimePercentileBreakdown {
+    #[serde(rename(serialize = "bySeverity"))]
+    pub by_severity: Vec<ResponseTimePercentiles>,
+    #[serde(rename(serialize = "byCategory"))]
+    pub by_category: Vec<ResponseTimePercentiles>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ResponseTimePercentiles {
+    #[serde(rename(serialize = "group"))]
+    pub grp: String,
+    #[serde(rename(serialize = "p50FirstResponse"))]
+    pub p50_first_response: Option<String>,
+    #[serde(rename(serialize = "p90FirstResponse"))]
+    pub p90_first_response: Option<String>,
+    #[serde(rename(serialize = "p99FirstResponse"))]
+    pub p99_first_response: Option<String>,
+    #[serde(rename(serialize = "p50Resolution"))]
+    pub p50_resolution: Option<String>,
+    #[serde(rename(serialize = "p90Resolution"))]
+    pub p90_resolution: Option<String>,
+    #[serde(rename(serialize = "p99Resolution"))]
+    pub p99_resolution: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct WeeklyStatsParams {
+    pub weeks: Option<i32>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct WeeklyStats {
     #[serde(rename(serialize = "dateRange"))]
     pub date_range: String,
     #[serde(rename(serialize = "totalIssues"))]
@@ -54,8 +301,393 @@ pub struct LastFourWeeksStats {
     pub average_response_time: Option<String>,
     #[serde(rename(serialize = "bestSolver"))]
     pub best_solver: Option<String>,
+    #[serde(rename(serialize = "bestSolverCount"))]
+    pub best_solver_count: Option<i64>,
     #[serde(rename(serialize = "bestFirstResponder"))]
     pub best_first_responder: Option<String>,
+    #[serde(rename(serialize = "bestFirstResponderCount"))]
+    pub best_first_responder_count: Option<i64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SlaComplianceStats {
+    pub week: String,
+    #[serde(rename(serialize = "totalIssues"))]
+    pub total_issues: i64,
+    #[serde(rename(serialize = "firstResponseMet"))]
+    pub first_response_met: i64,
+    #[serde(rename(serialize = "firstResponseBreached"))]
+    pub first_response_breached: i64,
+    #[serde(rename(serialize = "firstResponseCompliance"))]
+    pub first_response_compliance: Option<f64>,
+    #[serde(rename(serialize = "resolutionMet"))]
+    pub resolution_met: i64,
+    #[serde(rename(serialize = "resolutionBreached"))]
+    pub resolution_breached: i64,
+    #[serde(rename(serialize = "resolutionCompliance"))]
+    pub resolution_compliance: Option<f64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SeverityRule {
+    pub id: i32,
+    pub kind: String,
+    pub pattern: String,
+    pub severity: i16,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct WeeklyDigestStats {
+    pub new_issues: i64,
+    pub resolved_issues: i64,
+    pub elevated_issues: i64,
+    pub average_response_time: Option<String>,
+    pub unresolved_backlog: i64,
+    pub upvotes: i64,
+    pub downvotes: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TopUser {
+    pub name: String,
+    pub count: i64,
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub granularity: Option<Granularity>,
+    pub group_by: Option<StatsGroupBy>,
+    pub guild: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+
+    /// The longest from/to span a series can cover, so one request can't ask for an unbounded
+    /// number of buckets.
+    pub fn max_range_days(&self) -> i64 {
+        match self {
+            Granularity::Day => 366,
+            Granularity::Week => 5 * 366,
+            Granularity::Month => 20 * 366,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsGroupBy {
+    Category,
+    Severity,
+    Origin,
+}
+
+impl StatsGroupBy {
+    /// Query that adds a `grp` column to every issue. Issues with several categories are counted once per category.
+    fn grouped_issues_query(group_by: Option<StatsGroupBy>) -> &'static str {
+        match group_by {
+            None => "SELECT issues.*, 'all' as grp FROM issues",
+            Some(StatsGroupBy::Category) => {
+                "SELECT issues.*, COALESCE(category, 'uncategorised') as grp FROM issues
+            LEFT JOIN LATERAL unnest(issues.Categories) as category ON TRUE"
+            }
+            Some(StatsGroupBy::Severity) => {
+                "SELECT issues.*, CAST(issues.SevCat as varchar) as grp FROM issues"
+            }
+            Some(StatsGroupBy::Origin) => "SELECT issues.*, issues.Origin as grp FROM issues",
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct StatsSeriesPoint {
+    pub period: String,
+    #[serde(rename(serialize = "group"))]
+    pub grp: String,
+    pub opened: i64,
+    pub resolved: i64,
+    pub elevated: i64,
+    #[serde(rename(serialize = "medianFirstResponseSecs"))]
+    pub median_first_response_secs: Option<f64>,
+    #[serde(rename(serialize = "p90FirstResponseSecs"))]
+    pub p90_first_response_secs: Option<f64>,
+    #[serde(rename(serialize = "medianResolutionSecs"))]
+    pub median_resolution_secs: Option<f64>,
+    #[serde(rename(serialize = "p90ResolutionSecs"))]
+    pub p90_resolution_secs: Option<f64>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardPeriod {
+    #[name = "Last 7 days"]
+    Week,
+    #[name = "Last month"]
+    Month,
+    #[name = "Last 3 months"]
+    Quarter,
+    #[name = "Last year"]
+    Year,
+    #[name = "All time"]
+    All,
+}
+
+impl LeaderboardPeriod {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LeaderboardPeriod::Week => "last 7 days",
+            LeaderboardPeriod::Month => "last month",
+            LeaderboardPeriod::Quarter => "last 3 months",
+            LeaderboardPeriod::Year => "last year",
+            LeaderboardPeriod::All => "all time",
+        }
+    }
+
+    fn interval(&self) -> Option<&'static str> {
+        match self {
+            LeaderboardPeriod::Week => Some("7 days"),
+            LeaderboardPeriod::Month => Some("1 month"),
+            LeaderboardPeriod::Quarter => Some("3 months"),
+            LeaderboardPeriod::Year => Some("1 year"),
+            LeaderboardPeriod::All => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    pub period: Option<LeaderboardPeriod>,
+    pub limit: Option<i64>,
+    pub guild: Option<String>,
+}
+
+/// Contributions of a single user. Accepted solutions are resolved threads that got more upvotes than downvotes,
+/// and positive feedback is the number of upvotes on threads the user resolved.
+#[derive(Serialize, sqlx::FromRow)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    /// The Discord user ID, for linking to their profile. Only known for contributions recorded since
+    /// user IDs were added.
+    #[serde(rename(serialize = "userId"))]
+    pub user_id: Option<String>,
+    #[serde(rename(serialize = "firstResponses"))]
+    pub first_responses: i64,
+    #[serde(rename(serialize = "resolvedThreads"))]
+    pub resolved_threads: i64,
+    #[serde(rename(serialize = "acceptedSolutions"))]
+    pub accepted_solutions: i64,
+    #[serde(rename(serialize = "positiveFeedback"))]
+    pub positive_feedback: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ContributorThread {
+    #[serde(rename(serialize = "discordThreadLink"))]
+    pub discord_thread_link: Option<String>,
+    #[serde(rename(serialize = "githubLink"))]
+    pub github_link: Option<String>,
+    pub severity: i16,
+    pub resolved: bool,
+    #[serde(rename(serialize = "creationDate"))]
+    pub creation_date: String,
+    #[serde(rename(serialize = "originalPoster"))]
+    pub original_poster: bool,
+    #[serde(rename(serialize = "firstResponder"))]
+    pub first_responder: bool,
+    pub resolver: bool,
+    /// What they were called on this thread, for users with no totals.
+    #[serde(skip)]
+    pub username: String,
+}
+
+#[derive(Serialize)]
+pub struct ContributorProfile {
+    #[serde(flatten)]
+    pub totals: LeaderboardEntry,
+    pub history: Vec<ContributorThread>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct SettingOverride {
+    pub key: String,
+    pub value: Json<Value>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct GuildRecord {
+    pub guild_id: String,
+    pub name: String,
+    pub help_forum_ids: Vec<String>,
+    pub github_repo: Option<String>,
+    pub staff_channel_id: Option<String>,
+    pub settings: Json<serde_json::Map<String, Value>>,
+    pub dashboard_users: Vec<String>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct StaffRoleRecord {
+    pub guild_id: String,
+    pub role_id: String,
+    pub tier: String,
+}
+
+/// A help thread as found by `/refresh`. The message fields are `None` when they weren't read.
+pub struct BackfillThread {
+    pub thread_id: String,
+    pub thread_url: String,
+    pub guild_id: String,
+    pub original_poster: Option<String>,
+    pub original_poster_id: Option<String>,
+    pub initial_message: Option<String>,
+    pub first_response_user: Option<String>,
+    pub first_response_user_id: Option<String>,
+    pub first_response_time_date: Option<DateTime<Utc>>,
+    pub locked: bool,
+    pub archived: bool,
+    pub created: Option<DateTime<Utc>>,
+}
+
+/// A GitHub issue that wasn't elevated from Discord, as found by `/refresh`.
+pub struct BackfillGithubIssue {
+    pub github_link: String,
+    pub guild_id: String,
+    pub original_poster: String,
+    pub first_response_user: Option<String>,
+    pub first_response_time_date: Option<DateTime<Utc>>,
+    pub resolver_user: Option<String>,
+    pub resolved: bool,
+    pub resolved_time_date: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadState {
+    Active,
+    Archived,
+    Deleted,
+}
+
+impl ThreadState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThreadState::Active => "active",
+            ThreadState::Archived => "archived",
+            ThreadState::Deleted => "deleted",
+        }
+    }
+}
+
+/// A help thread's row, as far as the reconciler needs to know.
+#[derive(sqlx::FromRow)]
+pub struct KnownThread {
+    pub thread_id: String,
+    pub thread_state: Option<String>,
+    /// Whether the poster and first responder are filled in.
+    pub complete: bool,
+}
+
+/// What an upsert did to a row.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Upserted {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+impl Upserted {
+    fn from_inserted(inserted: Option<bool>) -> Self {
+        match inserted {
+            Some(true) => Upserted::Created,
+            Some(false) => Upserted::Updated,
+            None => Upserted::Unchanged,
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Job {
+    pub id: i32,
+    pub kind: String,
+    #[serde(rename(serialize = "guildId"))]
+    pub guild_id: String,
+    #[serde(rename(serialize = "startedBy"))]
+    pub started_by: Option<String>,
+    pub status: String,
+    pub stage: Option<String>,
+    #[serde(rename(serialize = "threadsChecked"))]
+    pub threads_checked: i32,
+    #[serde(rename(serialize = "issuesChecked"))]
+    pub issues_checked: i32,
+    #[serde(rename(serialize = "rowsCreated"))]
+    pub rows_created: i32,
+    #[serde(rename(serialize = "rowsUpdated"))]
+    pub rows_updated: i32,
+    #[serde(rename(serialize = "threadsLinked"))]
+    pub threads_linked: i32,
+    #[serde(rename(serialize = "errorCount"))]
+    pub error_count: i32,
+    pub errors: Vec<String>,
+    pub failure: Option<String>,
+    pub started: String,
+    pub finished: Option<String>,
+}
+
+/// How far a running job has got, saved as it goes.
+pub struct JobProgress {
+    pub stage: String,
+    pub threads_checked: i32,
+    pub issues_checked: i32,
+    pub rows_created: i32,
+    pub rows_updated: i32,
+    pub threads_linked: i32,
+    pub error_count: i32,
+    pub errors: Vec<String>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RecognitionRole {
+    pub role_id: String,
+    pub min_resolved_threads: Option<i32>,
+    pub min_accepted_solutions: Option<i32>,
+    pub window_days: i32,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ContributionCount {
+    pub user_id: String,
+    pub resolved_threads: i64,
+    pub accepted_solutions: i64,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct RecognitionRoleChange {
+    #[serde(rename(serialize = "userId"))]
+    pub user_id: String,
+    pub username: String,
+    #[serde(rename(serialize = "roleId"))]
+    pub role_id: String,
+    pub action: String,
+    #[serde(rename(serialize = "resolvedThreads"))]
+    pub resolved_threads: i64,
+    #[serde(rename(serialize = "acceptedSolutions"))]
+    pub accepted_solutions: i64,
+    pub timedate: String,
 }
 
 #[derive(Serialize, sqlx::FromRow)]
@@ -76,33 +708,139 @@ pub struct IssuesAwaitingResponse {
 }
 
 impl DBQueries {
-    pub async fn discord_get_feedback(
-        self, channel_id: String, upvotes: i32, downvotes: i32,
+    pub async fn ping(self) -> Result<(), String> {
+        let _timer = self.metrics.time_query("ping");
+        match sqlx::query("SELECT 1").execute(&self.db).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Error occurred while pinging the database: {e}")),
+        }
+    }
+
+    pub async fn discord_create_feedback_record(
+        self,
+        thread_id: String,
+        prompt_message_id: String,
+        responders: Vec<String>,
     ) -> Result<(), anyhow::Error> {
-         if let Err(e) = sqlx::query("INSERT INTO feedback (discordthreadid, upvotes, downvotes) VALUES ($1, $2, $3) 
-                    ON CONFLICT (discordthreadid) DO UPDATE SET upvotes = EXCLUDED.upvotes, downvotes = EXCLUDED.downvotes")
-            .bind(channel_id)
-            .bind(upvotes)
-            .bind(downvotes)
-            .execute(&self.db)
-            .await {
-                error!("Error when getting feedback: {e}");
-            }
+        let _timer = self.metrics.time_query("discord_create_feedback_record");
+        sqlx::query(
+            "INSERT INTO feedback (DiscordThreadId, PromptMessageId, Responders) VALUES ($1, $2, $3)
+        ON CONFLICT (DiscordThreadId) DO UPDATE SET
+        PromptMessageId = EXCLUDED.PromptMessageId,
+        Responders = EXCLUDED.Responders",
+        )
+        .bind(thread_id)
+        .bind(prompt_message_id)
+        .bind(responders)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn discord_set_feedback_helpful(
+        self,
+        thread_id: String,
+        submitted_by: String,
+        helpful: bool,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_set_feedback_helpful");
+        sqlx::query(
+            "INSERT INTO feedback (DiscordThreadId, Helpful, Upvotes, DownVotes, SubmittedBy, LastUpdated)
+        VALUES ($1, $2, CASE WHEN $2 THEN 1 ELSE 0 END, CASE WHEN $2 THEN 0 ELSE 1 END, $3, CURRENT_TIMESTAMP)
+        ON CONFLICT (DiscordThreadId) DO UPDATE SET
+        Helpful = EXCLUDED.Helpful,
+        Upvotes = EXCLUDED.Upvotes,
+        DownVotes = EXCLUDED.DownVotes,
+        SubmittedBy = EXCLUDED.SubmittedBy,
+        LastUpdated = EXCLUDED.LastUpdated",
+        )
+        .bind(thread_id)
+        .bind(helpful)
+        .bind(submitted_by)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn discord_get_feedback_thread(
+        self,
+        prompt_message_id: String,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_get_feedback_thread");
+        let thread_id = sqlx::query_scalar::<_, String>(
+            "SELECT DiscordThreadId FROM feedback WHERE PromptMessageId = $1",
+        )
+        .bind(prompt_message_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(thread_id)
+    }
+
+    /// Clears a vote, but only if it's still the one being removed (so removing a stale reaction doesn't undo a newer vote).
+    pub async fn discord_clear_feedback_helpful(
+        self,
+        thread_id: String,
+        helpful: bool,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_clear_feedback_helpful");
+        sqlx::query(
+            "UPDATE feedback SET
+        Helpful = NULL,
+        Upvotes = 0,
+        DownVotes = 0,
+        LastUpdated = CURRENT_TIMESTAMP
+        WHERE DiscordThreadId = $1 AND Helpful = $2",
+        )
+        .bind(thread_id)
+        .bind(helpful)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn discord_set_feedback_rating(
+        self,
+        thread_id: String,
+        submitted_by: String,
+        rating: i16,
+        comment: Option<String>,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_set_feedback_rating");
+        sqlx::query(
+            "INSERT INTO feedback (DiscordThreadId, Rating, Comment, SubmittedBy, LastUpdated)
+        VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+        ON CONFLICT (DiscordThreadId) DO UPDATE SET
+        Rating = EXCLUDED.Rating,
+        Comment = EXCLUDED.Comment,
+        SubmittedBy = EXCLUDED.SubmittedBy,
+        LastUpdated = EXCLUDED.LastUpdated",
+        )
+        .bind(thread_id)
+        .bind(rating)
+        .bind(comment)
+        .bind(submitted_by)
+        .execute(&self.db)
+        .await?;
 
         Ok(())
-        
     }
-    
+
     pub async fn discord_elevate_thread(
         self,
         github_issue_link: String,
         thread_url: String,
     ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_elevate_thread");
         if let Err(e) = sqlx::query(
             "UPDATE issues SET 
                 GithubLink = $1, 
                 Locked = TRUE,
-                LockStatusChangeReason = 'Thread was elevated to GitHub issue'
+                LockStatusChangeReason = 'Thread was elevated to GitHub issue',
+                ElevatedTimedate = COALESCE(ElevatedTimedate, CURRENT_TIMESTAMP)
                 WHERE DiscordThreadLink = $2",
         )
         .bind(github_issue_link)
@@ -125,6 +863,7 @@ impl DBQueries {
         reason: String,
         thread_url: String,
     ) -> Result<(), String> {
+        let _timer = self.metrics.time_query("discord_change_locked_status");
         if let Err(e) = sqlx::query(
             "UPDATE issues SET
         Locked = $1, 
@@ -150,17 +889,21 @@ impl DBQueries {
     pub async fn discord_resolve_thread(
         self,
         resolved_by: String,
+        resolver_id: String,
         thread_url: String,
         message_count: i32,
         usercount: i32,
     ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_resolve_thread");
         if let Err(e) = sqlx::query(
             "UPDATE issues SET
         Locked = true, 
+        Resolved = true,
         ResolverUser = $1,
+        ResolverUserId = $5,
         LockStatusChangeReason = 'Thread was resolved',
         messagecount = $2,
-        numberofusersinthread = $3,
+        usercount = $3,
         ResolvedTimedate = CURRENT_TIMESTAMP 
         WHERE DiscordThreadLink = $4",
         )
@@ -168,6 +911,7 @@ impl DBQueries {
         .bind(message_count)
         .bind(usercount)
         .bind(thread_url)
+        .bind(resolver_id)
         .execute(&self.db)
         .await
         {
@@ -182,18 +926,22 @@ impl DBQueries {
     pub async fn discord_update_initial_message(
         self,
         author: String,
+        author_id: String,
         contents: String,
         thread_url: String,
     ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_update_initial_message");
         if let Err(e) = sqlx::query(
             "UPDATE issues SET
                     OriginalPoster = $1, 
+                    OriginalPosterId = $4,
                     InitialMessage = $2 
                     WHERE DiscordThreadLink = $3",
         )
         .bind(author)
         .bind(contents)
         .bind(thread_url)
+        .bind(author_id)
         .execute(&self.db)
         .await
         {
@@ -208,16 +956,21 @@ impl DBQueries {
     pub async fn discord_get_first_response(
         self,
         message_owner: &str,
+        message_owner_id: String,
         thread_url: String,
     ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_get_first_response");
         if let Err(e) = sqlx::query(
             "UPDATE issues SET
                     FirstResponseUser = $1, 
+                    FirstResponseUserId = $3,
                     FirstResponseTimedate = CURRENT_TIMESTAMP 
-                    WHERE DiscordThreadLink = $2",
+                    WHERE DiscordThreadLink = $2
+                    AND FirstResponseUser IS NULL",
         )
         .bind(message_owner)
         .bind(thread_url)
+        .bind(message_owner_id)
         .execute(&self.db)
         .await
         {
@@ -235,15 +988,20 @@ impl DBQueries {
         thread_url: String,
         thread_id: String,
         categories: Vec<String>,
+        guild_id: String,
     ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_create_issue_record");
         if let Err(e) = sqlx::query("INSERT INTO issues (
             DiscordThreadId, 
             DiscordThreadLink,
-            Categories) 
-            VALUES ($1, $2, $3)")
+            Categories,
+            GuildId,
+            ThreadState) 
+            VALUES ($1, $2, $3, $4, 'active')")
             .bind(thread_id)
             .bind(thread_url)
             .bind(categories)
+            .bind(guild_id)
             .execute(&self.db)
             .await
         {
@@ -258,9 +1016,11 @@ impl DBQueries {
         severity: i32,
         thread_url: String,
     ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_set_catsev");
         if let Err(e) = sqlx::query(
             "UPDATE issues SET
-        SevCat = $1
+        SevCat = $1,
+        SevCatSource = 'manual'
         WHERE DiscordThreadLink = $2",
         )
         .bind(severity)
@@ -276,61 +1036,300 @@ impl DBQueries {
         Ok(())
     }
 
-    pub async fn get_last_four_weeks_stats(self) -> Result<Vec<LastFourWeeksStats>, String> {
-        match sqlx::query_as::<_, LastFourWeeksStats>("SELECT
-        CONCAT(to_date(concat(DATE_PART('year', date(created)), DATE_PART('week', date(created))), 'iyyyiw'),' - ',to_date(concat('2023', DATE_PART('week', date(created))), 'yyyyww') + 6) AS date_range,
-        COUNT(*) as total_issues,
-        (SELECT COUNT(*) FROM issues WHERE githubLink IS NOT NULL) as total_elevated_issues,
-        (SELECT COUNT(*) FROM issues WHERE resolved = TRUE) as total_resolved_issues,
-        (SELECT COUNT(*) FROM issues WHERE messagecount > 6 AND usercount >= 2) as total_one_touch_threads,
-        (SELECT COUNT(*) FROM issues WHERE messagecount > 50) as extended_threads,
-        CAST((SELECT date_trunc('second', AVG(firstresponsetimedate - created)) FROM issues) as varchar) as average_response_time,
-        (SELECT COUNT(ResolverUser) FROM issues WHERE resolved = True group by ResolverUser order by ResolverUser desc limit 1) as best_solver,
-        (SELECT COUNT(FirstResponseUser) FROM issues WHERE resolved = True group by FirstResponseUser order by FirstResponseUser desc limit 1) as best_first_responder
-        FROM issues
-        GROUP BY date_range 
-        ORDER BY date_range DESC
-        LIMIT 4
-        ")
-        .fetch_all(&self.db)
-        .await {
-            Ok(res) => Ok(res),
-            Err(e) => Err(format!("Error occurred while getting last 4 weeks stats: {e}"))
+    /// Sets a suggested severity on a thread, unless a staff member has already set one manually
+    /// or an earlier suggestion was more severe.
+    pub async fn discord_auto_set_catsev(
+        self,
+        severity: i16,
+        thread_url: String,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_auto_set_catsev");
+        if let Err(e) = sqlx::query(
+            "UPDATE issues SET
+        SevCat = $1,
+        SevCatSource = 'auto'
+        WHERE DiscordThreadLink = $2
+        AND (SevCatSource = 'default' OR (SevCatSource = 'auto' AND SevCat > $1))",
+        )
+        .bind(severity)
+        .bind(thread_url)
+        .execute(&self.db)
+        .await
+        {
+            error!(
+                "Error when updating SQL record with suggested severity: {:?}",
+                e
+            );
         }
+        Ok(())
     }
 
-    pub async fn get_issues_awaiting_response(self) -> Result<IssuesAwaitingResponse, String> {
-        match sqlx::query_as::<_, IssuesAwaitingResponse>("SELECT
-        (SELECT COUNT(*) FROM issues WHERE FirstResponseUser IS NULL) as unanswered_threads,
-        (SELECT COUNT(*) FROM issues WHERE Resolved = FALSE) as unresolved_issues,
-        (SELECT COUNT(*) FROM issues WHERE GithubLink IS NOT NULL and Resolved = FALSE) as unresolved_github_issues
-        FROM issues
-        ")
-        .fetch_one(&self.db)
-        .await {
-            Ok(res) => Ok(res),
-            Err(e) => Err(format!("Error occurred while getting issues awaiting response: {e}"))
-        }
+    /// Assigns a thread to a staff member and records it in the assignment history.
+    /// Returns false if there's no issue record for the thread.
+    pub async fn discord_assign_thread(
+        self,
+        assignee: Assignee,
+        assigned_by: String,
+        thread_url: String,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_assign_thread");
+        let res = sqlx::query(
+            "WITH updated AS (
+            UPDATE issues SET
+            AssignedTo = $1,
+            AssignedToId = $2
+            WHERE DiscordThreadLink = $4
+            RETURNING Id
+        )
+        INSERT INTO assignment_history (IssueId, AssignedTo, AssignedToId, AssignedBy)
+        SELECT Id, $1, $2, $3 FROM updated",
+        )
+        .bind(assignee.name)
+        .bind(assignee.id)
+        .bind(assigned_by)
+        .bind(thread_url)
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
     }
 
-    pub async fn get_issues_opened_last_7_days(self) -> Result<Vec<IssuesOpenedLastWeek>, String> {
-        match sqlx::query_as::<_, IssuesOpenedLastWeek>(
-            "with days as (
-        select generate_series(
-        date(current_timestamp) - 6,
-        date(current_timestamp),
-        '1 day'::interval
-            ) as day
+    pub async fn discord_get_assignee(
+        self,
+        thread_url: String,
+    ) -> Result<Option<Assignee>, anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_get_assignee");
+        let res = sqlx::query_as::<_, Assignee>(
+            "SELECT AssignedTo as name, AssignedToId as id
+        FROM issues
+        WHERE DiscordThreadLink = $1 AND AssignedToId IS NOT NULL",
         )
+        .bind(thread_url)
+        .fetch_optional(&self.db)
+        .await?;
 
-        select
-        CAST(date(days.day) as varchar) as day,
-        count(issues.id) as total_issues_per_day
-        from days
-        left join issues on date(created) = days.day
+        Ok(res)
+    }
+
+    pub async fn discord_get_assigned_threads(
+        self,
+        guild_id: String,
+        assignee_id: String,
+    ) -> Result<Vec<AssignedThread>, anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_get_assigned_threads");
+        let res = sqlx::query_as::<_, AssignedThread>(
+            "SELECT
+        DiscordThreadLink as discord_thread_link,
+        SevCat as severity,
+        CAST(DATE(created) as varchar) as creation_date
+        FROM issues
+        WHERE GuildId = $1 AND AssignedToId = $2 AND Resolved = FALSE AND DiscordThreadLink IS NOT NULL
+        ORDER BY SevCat ASC, created ASC",
+        )
+        .bind(guild_id)
+        .bind(assignee_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(res)
+    }
+
+    pub async fn discord_set_on_duty(
+        self,
+        guild_id: String,
+        user_id: String,
+        username: String,
+        on_duty: bool,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_set_on_duty");
+        sqlx::query(
+            "INSERT INTO staff_roster (GuildId, DiscordUserId, Username, OnDuty) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (GuildId, DiscordUserId) DO UPDATE SET
+        Username = EXCLUDED.Username,
+        OnDuty = EXCLUDED.OnDuty,
+        LastUpdated = CURRENT_TIMESTAMP",
+        )
+        .bind(guild_id)
+        .bind(user_id)
+        .bind(username)
+        .bind(on_duty)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Picks the next on-duty staff member to assign a new thread to, or None if nobody is on duty.
+    pub async fn pick_on_duty_assignee(
+        self,
+        guild_id: String,
+        strategy: AssignmentStrategy,
+    ) -> Result<Option<Assignee>, anyhow::Error> {
+        let _timer = self.metrics.time_query("pick_on_duty_assignee");
+        let next_assignee = match strategy {
+            AssignmentStrategy::RoundRobin => {
+                "SELECT DiscordUserId FROM staff_roster
+            WHERE GuildId = $1 AND OnDuty = TRUE
+            ORDER BY LastAssignedTimedate ASC NULLS FIRST, DiscordUserId ASC
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED"
+            }
+            AssignmentStrategy::LeastOpenThreads => {
+                "SELECT staff_roster.DiscordUserId FROM staff_roster
+            LEFT JOIN issues ON issues.AssignedToId = staff_roster.DiscordUserId
+                AND issues.GuildId = staff_roster.GuildId AND issues.Resolved = FALSE
+            WHERE staff_roster.GuildId = $1 AND staff_roster.OnDuty = TRUE
+            GROUP BY staff_roster.DiscordUserId, staff_roster.LastAssignedTimedate
+            ORDER BY COUNT(issues.Id) ASC, staff_roster.LastAssignedTimedate ASC NULLS FIRST, staff_roster.DiscordUserId ASC
+            LIMIT 1"
+            }
+        };
+
+        let res = sqlx::query_as::<_, Assignee>(&format!(
+            "UPDATE staff_roster SET
+            LastAssignedTimedate = CURRENT_TIMESTAMP
+            WHERE GuildId = $1 AND DiscordUserId = ({next_assignee})
+            RETURNING Username as name, DiscordUserId as id"
+        ))
+        .bind(guild_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_severity_rules(self, guild_id: String) -> Result<Vec<SeverityRule>, String> {
+        let _timer = self.metrics.time_query("get_severity_rules");
+        match sqlx::query_as::<_, SeverityRule>(
+            "SELECT Id as id, Kind as kind, Pattern as pattern, SevCat as severity
+        FROM severity_rules
+        WHERE GuildId = $1
+        ORDER BY Id",
+        )
+        .bind(guild_id)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while retrieving severity rules: {e}")),
+        }
+    }
+
+    pub async fn add_severity_rule(
+        self,
+        guild_id: String,
+        kind: &str,
+        pattern: String,
+        severity: i32,
+    ) -> Result<i32, anyhow::Error> {
+        let _timer = self.metrics.time_query("add_severity_rule");
+        let id = sqlx::query_scalar::<_, i32>(
+            "INSERT INTO severity_rules (GuildId, Kind, Pattern, SevCat) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (GuildId, Kind, Pattern) DO UPDATE SET SevCat = EXCLUDED.SevCat
+        RETURNING Id",
+        )
+        .bind(guild_id)
+        .bind(kind)
+        .bind(pattern)
+        .bind(severity as i16)
+        .fetch_one(&self.db)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn remove_severity_rule(
+        self,
+        guild_id: String,
+        id: i32,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("remove_severity_rule");
+        let res = sqlx::query("DELETE FROM severity_rules WHERE GuildId = $1 AND Id = $2")
+            .bind(guild_id)
+            .bind(id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn timezone_exists(self, timezone: &str) -> Result<bool, String> {
+        let _timer = self.metrics.time_query("timezone_exists");
+        match sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)",
+        )
+        .bind(timezone)
+        .fetch_one(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while checking timezone: {e}")),
+        }
+    }
+
+    /// Gets stats for each of the last `weeks` weeks (including the current one), where weeks start
+    /// on Monday in the given timezone. Every metric only counts issues from its own week:
+    /// issues are counted by the week they were created in, resolutions by the week they were resolved in
+    /// and first responses by the week they were made in.
+    pub async fn get_weekly_stats(
+        self,
+        weeks: i32,
+        timezone: String,
+        guild: Option<String>,
+    ) -> Result<Vec<WeeklyStats>, String> {
+        let _timer = self.metrics.time_query("get_weekly_stats");
+        match sqlx::query_as::<_, WeeklyStats>(WEEKLY_STATS_QUERY)
+        .bind(weeks)
+        .bind(timezone)
+        .bind(guild)
+        .fetch_all(&self.db)
+        .await {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting weekly stats: {e}"))
+        }
+    }
+
+    pub async fn get_issues_awaiting_response(
+        self,
+        guild: Option<String>,
+    ) -> Result<IssuesAwaitingResponse, String> {
+        let _timer = self.metrics.time_query("get_issues_awaiting_response");
+        match sqlx::query_as::<_, IssuesAwaitingResponse>("SELECT
+        (SELECT COUNT(*) FROM issues WHERE FirstResponseUser IS NULL AND ($1::varchar IS NULL OR GuildId = $1)) as unanswered_threads,
+        (SELECT COUNT(*) FROM issues WHERE Resolved = FALSE AND ($1::varchar IS NULL OR GuildId = $1)) as unresolved_issues,
+        (SELECT COUNT(*) FROM issues WHERE GithubLink IS NOT NULL and Resolved = FALSE AND ($1::varchar IS NULL OR GuildId = $1)) as unresolved_github_issues
+        ")
+        .bind(guild)
+        .fetch_one(&self.db)
+        .await {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting issues awaiting response: {e}"))
+        }
+    }
+
+    pub async fn get_issues_opened_last_7_days(
+        self,
+        guild: Option<String>,
+    ) -> Result<Vec<IssuesOpenedLastWeek>, String> {
+        let _timer = self.metrics.time_query("get_issues_opened_last_7_days");
+        match sqlx::query_as::<_, IssuesOpenedLastWeek>(
+            "with days as (
+        select generate_series(
+        date(current_timestamp) - 6,
+        date(current_timestamp),
+        '1 day'::interval
+            ) as day
+        )
+
+        select
+        CAST(date(days.day) as varchar) as day,
+        count(issues.id) as total_issues_per_day
+        from days
+        left join issues on date(created) = days.day and ($1::varchar IS NULL OR issues.GuildId = $1)
         group by 1
         order by day desc",
         )
+        .bind(guild)
         .fetch_all(&self.db)
         .await
         {
@@ -341,27 +1340,1575 @@ impl DBQueries {
         }
     }
 
-    pub async fn get_all_issues(self) -> Result<Vec<Issue>, String> {
-        match sqlx::query_as::<_, Issue>(
-            "SELECT 
-        OriginalPoster as original_poster,
-        DiscordThreadLink as discord_thread_link,
-        SevCat as severity, 
-        FirstResponseUser as first_responder,
-        GithubLink as github_link,
-        ResolverUser as resolved_by,
-        categories,
-        CAST(DATE(created) as varchar) as creation_date
-        from issues
+    pub async fn get_all_issues(self, filters: IssueFilters) -> Result<Vec<Issue>, String> {
+        let _timer = self.metrics.time_query("get_all_issues");
+        match sqlx::query_as::<_, Issue>(ISSUES_QUERY)
+            .bind(filters.assignee)
+            .bind(filters.guild)
+            .fetch_all(&self.db)
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!(
+                "Error occurred while retrieving list of issues: {e}"
+            )),
+        }
+    }
+
+    pub fn stream_issues<'a>(
+        &'a self,
+        filters: &'a IssueFilters,
+    ) -> BoxStream<'a, Result<Issue, sqlx::Error>> {
+        sqlx::query_as::<_, Issue>(ISSUES_QUERY)
+            .bind(filters.assignee.clone())
+            .bind(filters.guild.clone())
+            .fetch(&self.db)
+    }
+
+    pub fn stream_weekly_stats(
+        &self,
+        weeks: i32,
+        timezone: String,
+        guild: Option<String>,
+    ) -> BoxStream<'_, Result<WeeklyStats, sqlx::Error>> {
+        sqlx::query_as::<_, WeeklyStats>(WEEKLY_STATS_QUERY)
+            .bind(weeks)
+            .bind(timezone)
+            .bind(guild)
+            .fetch(&self.db)
+    }
+
+    /// SLA compliance for each of the last `weeks` weeks, with weeks starting on Monday in `timezone`.
+    pub async fn get_sla_compliance_stats(
+        self,
+        weeks: i32,
+        timezone: String,
+        guild: Option<String>,
+    ) -> Result<Vec<SlaComplianceStats>, String> {
+        let _timer = self.metrics.time_query("get_sla_compliance_stats");
+        match sqlx::query_as::<_, SlaComplianceStats>("SELECT
+        CAST(DATE(date_trunc('week', created AT TIME ZONE $2)) as varchar) as week,
+        COUNT(*) as total_issues,
+        COUNT(*) FILTER (WHERE NOT AwaitingFirstResponse AND NOT FirstResponseBreached) as first_response_met,
+        COUNT(*) FILTER (WHERE FirstResponseBreached) as first_response_breached,
+        CAST(100.0 * COUNT(*) FILTER (WHERE NOT AwaitingFirstResponse AND NOT FirstResponseBreached)
+            / NULLIF(COUNT(*) FILTER (WHERE NOT AwaitingFirstResponse OR FirstResponseBreached), 0) as DOUBLE PRECISION) as first_response_compliance,
+        COUNT(*) FILTER (WHERE Resolved AND NOT ResolutionBreached) as resolution_met,
+        COUNT(*) FILTER (WHERE ResolutionBreached) as resolution_breached,
+        CAST(100.0 * COUNT(*) FILTER (WHERE Resolved AND NOT ResolutionBreached)
+            / NULLIF(COUNT(*) FILTER (WHERE Resolved OR ResolutionBreached), 0) as DOUBLE PRECISION) as resolution_compliance
+        FROM issue_sla
+        WHERE created AT TIME ZONE $2 >= date_trunc('week', CURRENT_TIMESTAMP AT TIME ZONE $2) - ($1 - 1) * INTERVAL '1 week'
+        AND ($3::varchar IS NULL OR GuildId = $3)
+        GROUP BY week
+        ORDER BY week DESC
+        ")
+        .bind(weeks)
+        .bind(timezone)
+        .bind(guild)
+        .fetch_all(&self.db)
+        .await {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting SLA compliance stats: {e}"))
+        }
+    }
+
+    pub async fn get_sla_breaching_issues(
+        self,
+        guild: Option<String>,
+    ) -> Result<Vec<SlaBreach>, String> {
+        let _timer = self.metrics.time_query("get_sla_breaching_issues");
+        match sqlx::query_as::<_, SlaBreach>(
+            "SELECT
+        issues.DiscordThreadLink as discord_thread_link,
+        issues.GithubLink as github_link,
+        issues.SevCat as severity,
+        CAST(DATE(issues.created) as varchar) as creation_date,
+        issue_sla.AwaitingFirstResponse as awaiting_first_response,
+        CAST(date_trunc('second', NULLIF(issue_sla.FirstResponseBreachDuration, INTERVAL '0')) as varchar) as first_response_breach_duration,
+        CAST(date_trunc('second', NULLIF(issue_sla.ResolutionBreachDuration, INTERVAL '0')) as varchar) as resolution_breach_duration
+        FROM issues
+        JOIN issue_sla ON issue_sla.Id = issues.Id
+        WHERE issues.Resolved = FALSE
+        AND ((issue_sla.AwaitingFirstResponse AND issue_sla.FirstResponseBreached) OR issue_sla.ResolutionBreached)
+        AND ($1::varchar IS NULL OR issues.GuildId = $1)
+        ORDER BY issues.SevCat ASC, issues.created ASC
         ",
         )
+        .bind(guild)
         .fetch_all(&self.db)
         .await
         {
             Ok(res) => Ok(res),
             Err(e) => Err(format!(
-                "Error occurred while retrieving list of issues: {e}"
+                "Error occurred while retrieving SLA breaching issues: {e}"
+            )),
+        }
+    }
+
+    pub async fn set_sla_policy(
+        self,
+        guild_id: String,
+        severity: i32,
+        first_response_minutes: i32,
+        resolution_hours: i32,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("set_sla_policy");
+        if let Err(e) = sqlx::query(
+            "INSERT INTO sla_policies (GuildId, SevCat, FirstResponseTarget, ResolutionTarget)
+        VALUES ($1, $2, make_interval(mins => $3), make_interval(hours => $4))
+        ON CONFLICT (GuildId, SevCat) DO UPDATE SET
+        FirstResponseTarget = EXCLUDED.FirstResponseTarget,
+        ResolutionTarget = EXCLUDED.ResolutionTarget",
+        )
+        .bind(guild_id)
+        .bind(severity as i16)
+        .bind(first_response_minutes)
+        .bind(resolution_hours)
+        .execute(&self.db)
+        .await
+        {
+            error!("Error when updating SLA policy: {:?}", e);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    pub async fn get_assignee_stats(
+        self,
+        guild: Option<String>,
+    ) -> Result<Vec<AssigneeStats>, String> {
+        let _timer = self.metrics.time_query("get_assignee_stats");
+        match sqlx::query_as::<_, AssigneeStats>(
+            "SELECT
+        AssignedTo as assignee,
+        COUNT(*) FILTER (WHERE Resolved = FALSE) as open_issues,
+        COUNT(*) FILTER (WHERE Resolved = TRUE) as resolved_issues,
+        CAST(date_trunc('second', AVG(ResolvedTimedate - created) FILTER (WHERE Resolved = TRUE)) as varchar) as average_resolution_time
+        FROM issues
+        WHERE AssignedTo IS NOT NULL
+        AND ($1::varchar IS NULL OR GuildId = $1)
+        GROUP BY AssignedTo
+        ORDER BY open_issues DESC, assignee ASC
+        ",
+        )
+        .bind(guild)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!(
+                "Error occurred while getting assignee stats: {e}"
+            )),
+        }
+    }
+
+    pub async fn get_weekly_digest_stats(
+        self,
+        guild_id: String,
+    ) -> Result<WeeklyDigestStats, String> {
+        let _timer = self.metrics.time_query("get_weekly_digest_stats");
+        match sqlx::query_as::<_, WeeklyDigestStats>("SELECT
+        (SELECT COUNT(*) FROM issues WHERE GuildId = $1 AND created >= CURRENT_TIMESTAMP - INTERVAL '7 days') as new_issues,
+        (SELECT COUNT(*) FROM issues WHERE GuildId = $1 AND Resolved = TRUE AND ResolvedTimedate >= CURRENT_TIMESTAMP - INTERVAL '7 days') as resolved_issues,
+        (SELECT COUNT(*) FROM issues WHERE GuildId = $1 AND GithubLink IS NOT NULL AND Origin = 'discord' AND COALESCE(ElevatedTimedate, created) >= CURRENT_TIMESTAMP - INTERVAL '7 days') as elevated_issues,
+        (SELECT CAST(date_trunc('second', AVG(FirstResponseTimedate - created)) as varchar) FROM issues WHERE GuildId = $1 AND created >= CURRENT_TIMESTAMP - INTERVAL '7 days') as average_response_time,
+        (SELECT COUNT(*) FROM issues WHERE GuildId = $1 AND Resolved = FALSE) as unresolved_backlog,
+        (SELECT COALESCE(SUM(feedback.Upvotes), 0) FROM feedback JOIN issues ON issues.DiscordThreadId = feedback.DiscordThreadId
+            WHERE issues.GuildId = $1 AND issues.created >= CURRENT_TIMESTAMP - INTERVAL '7 days') as upvotes,
+        (SELECT COALESCE(SUM(feedback.DownVotes), 0) FROM feedback JOIN issues ON issues.DiscordThreadId = feedback.DiscordThreadId
+            WHERE issues.GuildId = $1 AND issues.created >= CURRENT_TIMESTAMP - INTERVAL '7 days') as downvotes
+        ")
+        .bind(guild_id)
+        .fetch_one(&self.db)
+        .await {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting weekly digest stats: {e}"))
+        }
+    }
+
+    pub async fn get_top_solvers(self, guild_id: String, limit: i64) -> Result<Vec<TopUser>, String> {
+        let _timer = self.metrics.time_query("get_top_solvers");
+        match sqlx::query_as::<_, TopUser>(
+            "SELECT
+        ResolverUser as name,
+        COUNT(*) as count
+        FROM issues
+        WHERE GuildId = $1
+        AND Resolved = TRUE
+        AND ResolverUser IS NOT NULL
+        AND ResolvedTimedate >= CURRENT_TIMESTAMP - INTERVAL '7 days'
+        GROUP BY ResolverUser
+        ORDER BY count DESC, name ASC
+        LIMIT $2
+        ",
+        )
+        .bind(guild_id)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting top solvers: {e}")),
+        }
+    }
+
+    /// Gets a time series of issue stats between two dates (inclusive, in UTC).
+    /// Issues are counted as opened in the period they were created in, resolved in the period they were resolved in
+    /// and elevated in the period they were elevated in. First response times are attributed to the period
+    /// the issue was created in and resolution times to the period it was resolved in.
+    pub async fn get_stats_series(self, query: StatsQuery) -> Result<Vec<StatsSeriesPoint>, String> {
+        let _timer = self.metrics.time_query("get_stats_series");
+        let granularity = query.granularity.unwrap_or(Granularity::Week);
+        let grouped_issues = StatsGroupBy::grouped_issues_query(query.group_by);
+
+        match sqlx::query_as::<_, StatsSeriesPoint>(&format!(
+            "WITH grouped AS ({grouped_issues}),
+        periods AS (
+            SELECT generate_series(
+                date_trunc($3, CAST($1 as timestamp)),
+                CAST($2 as timestamp),
+                CAST('1 ' || $3 as interval)
+            ) as period
+        ),
+        opened AS (
+            SELECT date_trunc($3, created AT TIME ZONE 'UTC') as period, grp,
+            COUNT(*) as opened,
+            percentile_cont(0.5) WITHIN GROUP (ORDER BY CAST(EXTRACT(EPOCH FROM FirstResponseTimedate - created) as DOUBLE PRECISION)) as median_first_response_secs,
+            percentile_cont(0.9) WITHIN GROUP (ORDER BY CAST(EXTRACT(EPOCH FROM FirstResponseTimedate - created) as DOUBLE PRECISION)) as p90_first_response_secs
+            FROM grouped
+            WHERE created AT TIME ZONE 'UTC' >= $1 AND created AT TIME ZONE 'UTC' < $2 + INTERVAL '1 day'
+            AND ($4::varchar IS NULL OR GuildId = $4)
+            GROUP BY 1, 2
+        ),
+        resolved AS (
+            SELECT date_trunc($3, ResolvedTimedate AT TIME ZONE 'UTC') as period, grp,
+            COUNT(*) as resolved,
+            percentile_cont(0.5) WITHIN GROUP (ORDER BY CAST(EXTRACT(EPOCH FROM ResolvedTimedate - created) as DOUBLE PRECISION)) as median_resolution_secs,
+            percentile_cont(0.9) WITHIN GROUP (ORDER BY CAST(EXTRACT(EPOCH FROM ResolvedTimedate - created) as DOUBLE PRECISION)) as p90_resolution_secs
+            FROM grouped
+            WHERE Resolved = TRUE
+            AND ResolvedTimedate AT TIME ZONE 'UTC' >= $1 AND ResolvedTimedate AT TIME ZONE 'UTC' < $2 + INTERVAL '1 day'
+            AND ($4::varchar IS NULL OR GuildId = $4)
+            GROUP BY 1, 2
+        ),
+        elevated AS (
+            SELECT date_trunc($3, COALESCE(ElevatedTimedate, created) AT TIME ZONE 'UTC') as period, grp,
+            COUNT(*) as elevated
+            FROM grouped
+            WHERE GithubLink IS NOT NULL AND Origin = 'discord'
+            AND COALESCE(ElevatedTimedate, created) AT TIME ZONE 'UTC' >= $1
+            AND COALESCE(ElevatedTimedate, created) AT TIME ZONE 'UTC' < $2 + INTERVAL '1 day'
+            AND ($4::varchar IS NULL OR GuildId = $4)
+            GROUP BY 1, 2
+        ),
+        groups AS (
+            SELECT grp FROM opened UNION SELECT grp FROM resolved UNION SELECT grp FROM elevated
+        )
+        SELECT
+        CAST(DATE(periods.period) as varchar) as period,
+        groups.grp,
+        COALESCE(opened.opened, 0) as opened,
+        COALESCE(resolved.resolved, 0) as resolved,
+        COALESCE(elevated.elevated, 0) as elevated,
+        opened.median_first_response_secs,
+        opened.p90_first_response_secs,
+        resolved.median_resolution_secs,
+        resolved.p90_resolution_secs
+        FROM periods
+        CROSS JOIN groups
+        LEFT JOIN opened ON opened.period = periods.period AND opened.grp = groups.grp
+        LEFT JOIN resolved ON resolved.period = periods.period AND resolved.grp = groups.grp
+        LEFT JOIN elevated ON elevated.period = periods.period AND elevated.grp = groups.grp
+        ORDER BY periods.period ASC, groups.grp ASC
+        "
+        ))
+        .bind(query.from)
+        .bind(query.to)
+        .bind(granularity.as_str())
+        .bind(query.guild)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting stats series: {e}")),
+        }
+    }
+
+    /// Gets p50/p90/p99 time to first response and time to resolution for each group.
+    /// Unanswered/unresolved issues are left out of the respective percentiles.
+    pub async fn get_response_time_percentiles(
+        self,
+        group_by: StatsGroupBy,
+        guild: Option<String>,
+    ) -> Result<Vec<ResponseTimePercentiles>, String> {
+        let _timer = self.metrics.time_query("get_response_time_percentiles");
+        let grouped_issues = StatsGroupBy::grouped_issues_query(Some(group_by));
+
+        match sqlx::query_as::<_, ResponseTimePercentiles>(&format!(
+            "WITH grouped AS ({grouped_issues})
+        SELECT
+        grp,
+        CAST(date_trunc('second', percentile_cont(0.5) WITHIN GROUP (ORDER BY FirstResponseTimedate - created)) as varchar) as p50_first_response,
+        CAST(date_trunc('second', percentile_cont(0.9) WITHIN GROUP (ORDER BY FirstResponseTimedate - created)) as varchar) as p90_first_response,
+        CAST(date_trunc('second', percentile_cont(0.99) WITHIN GROUP (ORDER BY FirstResponseTimedate - created)) as varchar) as p99_first_response,
+        CAST(date_trunc('second', percentile_cont(0.5) WITHIN GROUP (ORDER BY ResolvedTimedate - created) FILTER (WHERE Resolved = TRUE)) as varchar) as p50_resolution,
+        CAST(date_trunc('second', percentile_cont(0.9) WITHIN GROUP (ORDER BY ResolvedTimedate - created) FILTER (WHERE Resolved = TRUE)) as varchar) as p90_resolution,
+        CAST(date_trunc('second', percentile_cont(0.99) WITHIN GROUP (ORDER BY ResolvedTimedate - created) FILTER (WHERE Resolved = TRUE)) as varchar) as p99_resolution
+        FROM grouped
+        WHERE ($1::varchar IS NULL OR GuildId = $1)
+        GROUP BY grp
+        ORDER BY grp ASC
+        "
+        ))
+        .bind(guild)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!(
+                "Error occurred while getting response time percentiles: {e}"
             )),
         }
     }
+
+    /// Query that ranks users by their contributions to the guild bound to `$3`, on issues created in the
+    /// period bound to `$1` (all time if NULL). `filter` is added as a WHERE clause on the ranked rows.
+    fn leaderboard_query(filter: &str) -> String {
+        format!(
+            "WITH period_issues AS (
+            SELECT * FROM issues
+            WHERE GuildId = $3
+            AND (CAST($1 as interval) IS NULL OR created >= CURRENT_TIMESTAMP - CAST($1 as interval))
+        ),
+        first_responses AS (
+            SELECT FirstResponseUser as name, MAX(FirstResponseUserId) as user_id, COUNT(*) as first_responses
+            FROM period_issues
+            WHERE FirstResponseUser IS NOT NULL
+            GROUP BY FirstResponseUser
+        ),
+        resolutions AS (
+            SELECT ResolverUser as name,
+            MAX(ResolverUserId) as user_id,
+            COUNT(*) as resolved_threads,
+            COUNT(*) FILTER (WHERE feedback.Upvotes > feedback.DownVotes) as accepted_solutions,
+            COALESCE(SUM(feedback.Upvotes), 0) as positive_feedback
+            FROM period_issues
+            LEFT JOIN feedback ON feedback.DiscordThreadId = period_issues.DiscordThreadId
+            WHERE Resolved = TRUE AND ResolverUser IS NOT NULL
+            GROUP BY ResolverUser
+        ),
+        ranked AS (
+            SELECT
+            COALESCE(first_responses.name, resolutions.name) as name,
+            COALESCE(first_responses.user_id, resolutions.user_id) as user_id,
+            COALESCE(first_responses.first_responses, 0) as first_responses,
+            COALESCE(resolutions.resolved_threads, 0) as resolved_threads,
+            COALESCE(resolutions.accepted_solutions, 0) as accepted_solutions,
+            COALESCE(resolutions.positive_feedback, 0) as positive_feedback
+            FROM first_responses
+            FULL OUTER JOIN resolutions ON resolutions.name = first_responses.name
+        )
+        SELECT * FROM ranked
+        WHERE {filter}
+        ORDER BY resolved_threads DESC, accepted_solutions DESC, first_responses DESC, positive_feedback DESC, name ASC
+        LIMIT $2"
+        )
+    }
+
+    pub async fn get_leaderboard(
+        self,
+        guild_id: String,
+        period: LeaderboardPeriod,
+        limit: i64,
+    ) -> Result<Vec<LeaderboardEntry>, String> {
+        let _timer = self.metrics.time_query("get_leaderboard");
+        match sqlx::query_as::<_, LeaderboardEntry>(&Self::leaderboard_query("TRUE"))
+            .bind(period.interval())
+            .bind(limit)
+            .bind(guild_id)
+            .fetch_all(&self.db)
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting leaderboard: {e}")),
+        }
+    }
+
+    /// Gets a user's all-time contributions to a guild and the guild's threads they've taken part in,
+    /// or None if they haven't taken part in any.
+    pub async fn get_contributor_profile(
+        self,
+        guild_id: String,
+        user_id: String,
+    ) -> Result<Option<ContributorProfile>, String> {
+        let _timer = self.metrics.time_query("get_contributor_profile");
+        let totals = match sqlx::query_as::<_, LeaderboardEntry>(&Self::leaderboard_query(
+            "user_id = $4",
+        ))
+        .bind(LeaderboardPeriod::All.interval())
+        .bind(1_i64)
+        .bind(&guild_id)
+        .bind(&user_id)
+        .fetch_optional(&self.db)
+        .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                return Err(format!(
+                    "Error occurred while getting contributor totals: {e}"
+                ))
+            }
+        };
+
+        let history = match sqlx::query_as::<_, ContributorThread>(
+            "SELECT
+        DiscordThreadLink as discord_thread_link,
+        GithubLink as github_link,
+        SevCat as severity,
+        Resolved as resolved,
+        CAST(DATE(created) as varchar) as creation_date,
+        COALESCE(OriginalPosterId = $1, FALSE) as original_poster,
+        COALESCE(FirstResponseUserId = $1, FALSE) as first_responder,
+        COALESCE(ResolverUserId = $1, FALSE) as resolver,
+        COALESCE(CASE
+            WHEN OriginalPosterId = $1 THEN OriginalPoster
+            WHEN FirstResponseUserId = $1 THEN FirstResponseUser
+            ELSE ResolverUser
+        END, '') as username
+        FROM issues
+        WHERE GuildId = $2 AND $1 IN (OriginalPosterId, FirstResponseUserId, ResolverUserId)
+        ORDER BY created DESC
+        LIMIT 100
+        ",
+        )
+        .bind(&user_id)
+        .bind(guild_id)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                return Err(format!(
+                    "Error occurred while getting contributor history: {e}"
+                ))
+            }
+        };
+
+        let totals = match (totals, history.first()) {
+            (Some(totals), _) => totals,
+            (None, Some(thread)) => LeaderboardEntry {
+                name: thread.username.clone(),
+                user_id: Some(user_id),
+                first_responses: 0,
+                resolved_threads: 0,
+                accepted_solutions: 0,
+                positive_feedback: 0,
+            },
+            (None, None) => return Ok(None),
+        };
+
+        Ok(Some(ContributorProfile { totals, history }))
+    }
+
+    pub async fn get_recognition_roles(
+        self,
+        guild_id: String,
+    ) -> Result<Vec<RecognitionRole>, anyhow::Error> {
+        let _timer = self.metrics.time_query("get_recognition_roles");
+        let res = sqlx::query_as::<_, RecognitionRole>(
+            "SELECT
+        RoleId as role_id,
+        MinResolvedThreads as min_resolved_threads,
+        MinAcceptedSolutions as min_accepted_solutions,
+        WindowDays as window_days
+        FROM recognition_roles
+        WHERE GuildId = $1",
+        )
+        .bind(guild_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(res)
+    }
+
+    pub async fn set_recognition_role(
+        self,
+        guild_id: String,
+        role: RecognitionRole,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("set_recognition_role");
+        sqlx::query(
+            "INSERT INTO recognition_roles (GuildId, RoleId, MinResolvedThreads, MinAcceptedSolutions, WindowDays)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (RoleId) DO UPDATE SET
+        MinResolvedThreads = EXCLUDED.MinResolvedThreads,
+        MinAcceptedSolutions = EXCLUDED.MinAcceptedSolutions,
+        WindowDays = EXCLUDED.WindowDays",
+        )
+        .bind(guild_id)
+        .bind(role.role_id)
+        .bind(role.min_resolved_threads)
+        .bind(role.min_accepted_solutions)
+        .bind(role.window_days)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_recognition_role(
+        self,
+        guild_id: String,
+        role_id: String,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("remove_recognition_role");
+        let res = sqlx::query("DELETE FROM recognition_roles WHERE GuildId = $1 AND RoleId = $2")
+            .bind(guild_id)
+            .bind(role_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Gets resolved threads and accepted solutions per user over the last `window_days` days.
+    pub async fn get_contribution_counts(
+        self,
+        guild_id: String,
+        window_days: i32,
+    ) -> Result<Vec<ContributionCount>, anyhow::Error> {
+        let _timer = self.metrics.time_query("get_contribution_counts");
+        let res = sqlx::query_as::<_, ContributionCount>(
+            "SELECT
+        ResolverUserId as user_id,
+        COUNT(*) as resolved_threads,
+        COUNT(*) FILTER (WHERE feedback.Upvotes > feedback.DownVotes) as accepted_solutions
+        FROM issues
+        LEFT JOIN feedback ON feedback.DiscordThreadId = issues.DiscordThreadId
+        WHERE issues.GuildId = $1
+        AND Resolved = TRUE
+        AND ResolverUserId IS NOT NULL
+        AND ResolvedTimedate >= CURRENT_TIMESTAMP - make_interval(days => $2)
+        GROUP BY ResolverUserId",
+        )
+        .bind(guild_id)
+        .bind(window_days)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(res)
+    }
+
+    /// Gets the IDs of users who currently hold a recognition role because the bot granted it to them.
+    pub async fn get_recognition_role_holders(
+        self,
+        role_id: String,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let _timer = self.metrics.time_query("get_recognition_role_holders");
+        let res = sqlx::query_scalar::<_, String>(
+            "SELECT DiscordUserId FROM (
+            SELECT DISTINCT ON (DiscordUserId) DiscordUserId, Action
+            FROM recognition_role_changes
+            WHERE RoleId = $1
+            ORDER BY DiscordUserId, Timedate DESC
+        ) latest
+        WHERE Action = 'granted'",
+        )
+        .bind(role_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(res)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn log_recognition_role_change(
+        self,
+        guild_id: String,
+        user_id: String,
+        username: String,
+        role_id: String,
+        action: &str,
+        resolved_threads: i64,
+        accepted_solutions: i64,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("log_recognition_role_change");
+        sqlx::query(
+            "INSERT INTO recognition_role_changes
+        (GuildId, DiscordUserId, Username, RoleId, Action, ResolvedThreads, AcceptedSolutions)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(guild_id)
+        .bind(user_id)
+        .bind(username)
+        .bind(role_id)
+        .bind(action)
+        .bind(resolved_threads)
+        .bind(accepted_solutions)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_recognition_role_changes(
+        self,
+        limit: i64,
+        guild: Option<String>,
+    ) -> Result<Vec<RecognitionRoleChange>, String> {
+        let _timer = self.metrics.time_query("get_recognition_role_changes");
+        match sqlx::query_as::<_, RecognitionRoleChange>(
+            "SELECT
+        DiscordUserId as user_id,
+        Username as username,
+        RoleId as role_id,
+        Action as action,
+        ResolvedThreads as resolved_threads,
+        AcceptedSolutions as accepted_solutions,
+        CAST(date_trunc('second', Timedate) as varchar) as timedate
+        FROM recognition_role_changes
+        WHERE ($2::varchar IS NULL OR GuildId = $2)
+        ORDER BY Timedate DESC
+        LIMIT $1",
+        )
+        .bind(limit)
+        .bind(guild)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!(
+                "Error occurred while getting recognition role changes: {e}"
+            )),
+        }
+    }
+
+    pub async fn get_feedback_summary(
+        self,
+        guild: Option<String>,
+    ) -> Result<FeedbackSummary, String> {
+        let _timer = self.metrics.time_query("get_feedback_summary");
+        match sqlx::query_as::<_, FeedbackSummary>(
+            "SELECT
+        COUNT(*) FILTER (WHERE feedback.Helpful IS NOT NULL OR feedback.Rating IS NOT NULL) as total_responses,
+        COUNT(*) FILTER (WHERE feedback.Helpful = TRUE) as helpful,
+        COUNT(*) FILTER (WHERE feedback.Helpful = FALSE) as not_helpful,
+        CAST(AVG(feedback.Rating) as DOUBLE PRECISION) as average_rating
+        FROM feedback
+        LEFT JOIN issues ON issues.DiscordThreadId = feedback.DiscordThreadId
+        WHERE ($1::varchar IS NULL OR issues.GuildId = $1)
+        ",
+        )
+        .bind(guild)
+        .fetch_one(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting feedback summary: {e}")),
+        }
+    }
+
+    pub async fn get_recent_feedback_comments(
+        self,
+        limit: i64,
+        guild: Option<String>,
+    ) -> Result<Vec<FeedbackComment>, String> {
+        let _timer = self.metrics.time_query("get_recent_feedback_comments");
+        match sqlx::query_as::<_, FeedbackComment>(
+            "SELECT
+        issues.DiscordThreadLink as discord_thread_link,
+        feedback.Helpful as helpful,
+        feedback.Rating as rating,
+        feedback.Comment as comment,
+        feedback.Responders as responders,
+        feedback.SubmittedBy as submitted_by,
+        CAST(DATE(COALESCE(feedback.LastUpdated, feedback.Created)) as varchar) as date
+        FROM feedback
+        LEFT JOIN issues ON issues.DiscordThreadId = feedback.DiscordThreadId
+        WHERE (feedback.Rating IS NOT NULL OR feedback.Comment IS NOT NULL)
+        AND ($2::varchar IS NULL OR issues.GuildId = $2)
+        ORDER BY COALESCE(feedback.LastUpdated, feedback.Created) DESC
+        LIMIT $1
+        ",
+        )
+        .bind(limit)
+        .bind(guild)
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting feedback comments: {e}")),
+        }
+    }
+
+    pub async fn get_settings(self) -> Result<Vec<SettingOverride>, String> {
+        let _timer = self.metrics.time_query("get_settings");
+        match sqlx::query_as::<_, SettingOverride>("SELECT Key as key, Value as value FROM settings")
+            .fetch_all(&self.db)
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting settings: {e}")),
+        }
+    }
+
+    pub async fn set_setting(self, key: String, value: Value) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("set_setting");
+        sqlx::query(
+            "INSERT INTO settings (Key, Value) VALUES ($1, $2)
+        ON CONFLICT (Key) DO UPDATE SET Value = EXCLUDED.Value, LastUpdated = CURRENT_TIMESTAMP",
+        )
+        .bind(key)
+        .bind(Json(value))
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_guilds(self) -> Result<Vec<GuildRecord>, String> {
+        let _timer = self.metrics.time_query("get_guilds");
+        match sqlx::query_as::<_, GuildRecord>(
+            "SELECT
+        GuildId as guild_id,
+        Name as name,
+        HelpForumIds as help_forum_ids,
+        GithubRepo as github_repo,
+        StaffChannelId as staff_channel_id,
+        Settings as settings,
+        DashboardUsers as dashboard_users
+        FROM guilds
+        ORDER BY Created ASC",
+        )
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting guilds: {e}")),
+        }
+    }
+
+    /// Adds the guild from the deployment's secrets with its staff role as a maintainer role,
+    /// leaving it alone if it's already been set up.
+    pub async fn seed_guild(
+        self,
+        guild_id: String,
+        staff_role_id: String,
+        staff_channel_id: Option<String>,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("seed_guild");
+        sqlx::query(
+            "WITH guild AS (
+            INSERT INTO guilds (GuildId, Name, StaffChannelId) VALUES ($1, $1, $3)
+            ON CONFLICT (GuildId) DO NOTHING
+            RETURNING GuildId
+        ), staff_role AS (
+            INSERT INTO staff_roles (GuildId, RoleId, Tier)
+            SELECT GuildId, $2, 'maintainer' FROM guild
+        )
+        UPDATE guilds SET StaffChannelId = $3
+        WHERE GuildId = $1 AND StaffChannelId IS NULL",
+        )
+        .bind(guild_id)
+        .bind(staff_role_id)
+        .bind(staff_channel_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gives severity rules, the on-duty roster and recognition roles set up before guilds existed
+    /// to the guild from the secrets. SLA policies without a guild are left as the defaults.
+    pub async fn claim_unscoped_rows(self, guild_id: String) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("claim_unscoped_rows");
+        sqlx::query(
+            "WITH severity_rules AS (
+            UPDATE severity_rules SET GuildId = $1 WHERE GuildId IS NULL
+        ), staff_roster AS (
+            UPDATE staff_roster SET GuildId = $1 WHERE GuildId IS NULL
+        ), recognition_roles AS (
+            UPDATE recognition_roles SET GuildId = $1 WHERE GuildId IS NULL
+        )
+        UPDATE recognition_role_changes SET GuildId = $1 WHERE GuildId IS NULL",
+        )
+        .bind(guild_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn discord_set_guild_staff_channel(
+        self,
+        guild_id: String,
+        channel_id: String,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_set_guild_staff_channel");
+        sqlx::query("UPDATE guilds SET StaffChannelId = $2 WHERE GuildId = $1")
+            .bind(guild_id)
+            .bind(channel_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Adds or updates the guild, making the staff role a maintainer role.
+    pub async fn discord_setup_guild(
+        self,
+        guild_id: String,
+        name: String,
+        staff_role_id: String,
+        help_forum_id: String,
+        github_repo: Option<String>,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_setup_guild");
+        sqlx::query(
+            "WITH guild AS (
+            INSERT INTO guilds (GuildId, Name, HelpForumIds, GithubRepo)
+            VALUES ($1, $2, ARRAY[$4], $5)
+            ON CONFLICT (GuildId) DO UPDATE SET
+            Name = EXCLUDED.Name,
+            HelpForumIds = CASE WHEN $4 = ANY(guilds.HelpForumIds) THEN guilds.HelpForumIds
+                ELSE array_append(guilds.HelpForumIds, $4) END,
+            GithubRepo = COALESCE(EXCLUDED.GithubRepo, guilds.GithubRepo)
+            RETURNING GuildId
+        )
+        INSERT INTO staff_roles (GuildId, RoleId, Tier)
+        SELECT GuildId, $3, 'maintainer' FROM guild
+        ON CONFLICT (GuildId, RoleId) DO UPDATE SET Tier = EXCLUDED.Tier",
+        )
+        .bind(guild_id)
+        .bind(name)
+        .bind(staff_role_id)
+        .bind(help_forum_id)
+        .bind(github_repo)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns false if the forum was already being watched.
+    pub async fn discord_add_guild_forum(
+        self,
+        guild_id: String,
+        forum_id: String,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_add_guild_forum");
+        let res = sqlx::query(
+            "UPDATE guilds SET HelpForumIds = array_append(HelpForumIds, $2)
+        WHERE GuildId = $1 AND NOT ($2 = ANY(HelpForumIds))",
+        )
+        .bind(guild_id)
+        .bind(forum_id)
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Returns false if the user could already view the guild.
+    pub async fn discord_add_guild_dashboard_user(
+        self,
+        guild_id: String,
+        github_login: String,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("discord_add_guild_dashboard_user");
+        let res = sqlx::query(
+            "UPDATE guilds SET DashboardUsers = array_append(DashboardUsers, $2)
+        WHERE GuildId = $1 AND NOT ($2 = ANY(DashboardUsers))",
+        )
+        .bind(guild_id)
+        .bind(github_login)
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn set_guild_setting(
+        self,
+        guild_id: String,
+        key: String,
+        value: Value,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("set_guild_setting");
+        sqlx::query("UPDATE guilds SET Settings = jsonb_set(Settings, ARRAY[$2], $3) WHERE GuildId = $1")
+            .bind(guild_id)
+            .bind(key)
+            .bind(Json(value))
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_staff_roles(self) -> Result<Vec<StaffRoleRecord>, String> {
+        let _timer = self.metrics.time_query("get_staff_roles");
+        match sqlx::query_as::<_, StaffRoleRecord>(
+            "SELECT GuildId as guild_id, RoleId as role_id, Tier as tier FROM staff_roles",
+        )
+        .fetch_all(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting staff roles: {e}")),
+        }
+    }
+
+    pub async fn set_staff_role(
+        self,
+        guild_id: String,
+        role_id: String,
+        tier: String,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("set_staff_role");
+        sqlx::query(
+            "INSERT INTO staff_roles (GuildId, RoleId, Tier) VALUES ($1, $2, $3)
+        ON CONFLICT (GuildId, RoleId) DO UPDATE SET Tier = EXCLUDED.Tier",
+        )
+        .bind(guild_id)
+        .bind(role_id)
+        .bind(tier)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns false if the role wasn't a staff role.
+    pub async fn remove_staff_role(
+        self,
+        guild_id: String,
+        role_id: String,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("remove_staff_role");
+        let res = sqlx::query("DELETE FROM staff_roles WHERE GuildId = $1 AND RoleId = $2")
+            .bind(guild_id)
+            .bind(role_id)
+            .execute(&self.db)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Threads in the guild whose poster and first responder are already known, so `/refresh`
+    /// doesn't need to read their messages again.
+    pub async fn backfill_get_complete_threads(
+        self,
+        guild_id: String,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let _timer = self.metrics.time_query("backfill_get_complete_threads");
+        let thread_ids = sqlx::query_scalar::<_, String>(
+            "SELECT DiscordThreadId FROM issues
+        WHERE GuildId = $1
+        AND DiscordThreadId IS NOT NULL
+        AND OriginalPosterId IS NOT NULL
+        AND FirstResponseUserId IS NOT NULL",
+        )
+        .bind(guild_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(thread_ids)
+    }
+
+    /// Adds the thread, or updates the fields that have changed. Fields that weren't read are left
+    /// alone, and a first response the bot already recorded is never replaced.
+    pub async fn backfill_thread(self, thread: BackfillThread) -> Result<Upserted, anyhow::Error> {
+        let _timer = self.metrics.time_query("backfill_thread");
+        let thread_state = if thread.archived {
+            ThreadState::Archived
+        } else {
+            ThreadState::Active
+        };
+        let inserted = sqlx::query_scalar::<_, bool>(
+            "INSERT INTO issues (
+            DiscordThreadId,
+            DiscordThreadLink,
+            GuildId,
+            OriginalPoster,
+            InitialMessage,
+            FirstResponseUser,
+            FirstResponseTimedate,
+            Locked,
+            ThreadState,
+            Created,
+            OriginalPosterId,
+            FirstResponseUserId)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, COALESCE($10, CURRENT_TIMESTAMP), $11, $12)
+        ON CONFLICT (DiscordThreadId) DO UPDATE SET
+            GuildId = COALESCE(issues.GuildId, EXCLUDED.GuildId),
+            OriginalPoster = COALESCE(EXCLUDED.OriginalPoster, issues.OriginalPoster),
+            OriginalPosterId = COALESCE(EXCLUDED.OriginalPosterId, issues.OriginalPosterId),
+            InitialMessage = COALESCE(EXCLUDED.InitialMessage, issues.InitialMessage),
+            FirstResponseUser = COALESCE(issues.FirstResponseUser, EXCLUDED.FirstResponseUser),
+            FirstResponseUserId = COALESCE(issues.FirstResponseUserId, EXCLUDED.FirstResponseUserId),
+            FirstResponseTimedate = COALESCE(issues.FirstResponseTimedate, EXCLUDED.FirstResponseTimedate),
+            Locked = EXCLUDED.Locked,
+            ThreadState = EXCLUDED.ThreadState,
+            LastUpdated = CURRENT_TIMESTAMP
+        WHERE (
+            issues.GuildId,
+            issues.OriginalPoster,
+            issues.OriginalPosterId,
+            issues.InitialMessage,
+            issues.FirstResponseUser,
+            issues.FirstResponseUserId,
+            issues.FirstResponseTimedate,
+            issues.Locked,
+            issues.ThreadState
+        ) IS DISTINCT FROM (
+            COALESCE(issues.GuildId, EXCLUDED.GuildId),
+            COALESCE(EXCLUDED.OriginalPoster, issues.OriginalPoster),
+            COALESCE(EXCLUDED.OriginalPosterId, issues.OriginalPosterId),
+            COALESCE(EXCLUDED.InitialMessage, issues.InitialMessage),
+            COALESCE(issues.FirstResponseUser, EXCLUDED.FirstResponseUser),
+            COALESCE(issues.FirstResponseUserId, EXCLUDED.FirstResponseUserId),
+            COALESCE(issues.FirstResponseTimedate, EXCLUDED.FirstResponseTimedate),
+            EXCLUDED.Locked,
+            EXCLUDED.ThreadState
+        )
+        RETURNING (xmax = 0)",
+        )
+        .bind(thread.thread_id)
+        .bind(thread.thread_url)
+        .bind(thread.guild_id)
+        .bind(thread.original_poster)
+        .bind(thread.initial_message)
+        .bind(thread.first_response_user)
+        .bind(thread.first_response_time_date)
+        .bind(thread.locked)
+        .bind(thread_state.as_str())
+        .bind(thread.created)
+        .bind(thread.original_poster_id)
+        .bind(thread.first_response_user_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(Upserted::from_inserted(inserted))
+    }
+
+    /// Adds the GitHub issue, or updates the fields that have changed. Rows that came from Discord
+    /// are never touched.
+    pub async fn backfill_github_issue(
+        self,
+        issue: BackfillGithubIssue,
+    ) -> Result<Upserted, anyhow::Error> {
+        let _timer = self.metrics.time_query("backfill_github_issue");
+        let inserted = sqlx::query_scalar::<_, bool>(
+            "INSERT INTO issues (
+            Origin,
+            GithubLink,
+            GuildId,
+            OriginalPoster,
+            FirstResponseUser,
+            FirstResponseTimedate,
+            ResolverUser,
+            Locked,
+            Resolved,
+            ResolvedTimedate,
+            Created)
+            VALUES ('github', $1, $2, $3, $4, $5, $6, $7, $7, $8, $9)
+        ON CONFLICT (GithubLink) DO UPDATE SET
+            GuildId = COALESCE(issues.GuildId, EXCLUDED.GuildId),
+            OriginalPoster = EXCLUDED.OriginalPoster,
+            FirstResponseUser = EXCLUDED.FirstResponseUser,
+            FirstResponseTimedate = EXCLUDED.FirstResponseTimedate,
+            ResolverUser = EXCLUDED.ResolverUser,
+            Locked = EXCLUDED.Locked,
+            Resolved = EXCLUDED.Resolved,
+            ResolvedTimedate = EXCLUDED.ResolvedTimedate,
+            Created = EXCLUDED.Created,
+            LastUpdated = CURRENT_TIMESTAMP
+        WHERE issues.Origin = 'github'
+        AND (
+            issues.GuildId,
+            issues.OriginalPoster,
+            issues.FirstResponseUser,
+            issues.FirstResponseTimedate,
+            issues.ResolverUser,
+            issues.Resolved,
+            issues.ResolvedTimedate,
+            issues.Created
+        ) IS DISTINCT FROM (
+            COALESCE(issues.GuildId, EXCLUDED.GuildId),
+            EXCLUDED.OriginalPoster,
+            EXCLUDED.FirstResponseUser,
+            EXCLUDED.FirstResponseTimedate,
+            EXCLUDED.ResolverUser,
+            EXCLUDED.Resolved,
+            EXCLUDED.ResolvedTimedate,
+            EXCLUDED.Created
+        )
+        RETURNING (xmax = 0)",
+        )
+        .bind(issue.github_link)
+        .bind(issue.guild_id)
+        .bind(issue.original_poster)
+        .bind(issue.first_response_user)
+        .bind(issue.first_response_time_date)
+        .bind(issue.resolver_user)
+        .bind(issue.resolved)
+        .bind(issue.resolved_time_date)
+        .bind(issue.created)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(Upserted::from_inserted(inserted))
+    }
+
+    /// Links a thread to the issue it was elevated to. Returns false if there's no such thread or
+    /// it was already linked.
+    pub async fn backfill_link_elevated_issue(
+        self,
+        thread_url: String,
+        github_link: String,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("backfill_link_elevated_issue");
+        let res = sqlx::query(
+            "UPDATE issues SET GithubLink = $1, LastUpdated = CURRENT_TIMESTAMP
+        WHERE DiscordThreadLink = $2 AND GithubLink IS DISTINCT FROM $1",
+        )
+        .bind(github_link)
+        .bind(thread_url)
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn get_backfill_checkpoint(
+        self,
+        guild_id: String,
+        source: String,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let _timer = self.metrics.time_query("get_backfill_checkpoint");
+        let cursor = sqlx::query_scalar::<_, String>(
+            "SELECT Cursor FROM backfill_checkpoints WHERE GuildId = $1 AND Source = $2",
+        )
+        .bind(guild_id)
+        .bind(source)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(cursor)
+    }
+
+    pub async fn set_backfill_checkpoint(
+        self,
+        guild_id: String,
+        source: String,
+        cursor: String,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("set_backfill_checkpoint");
+        sqlx::query(
+            "INSERT INTO backfill_checkpoints (GuildId, Source, Cursor) VALUES ($1, $2, $3)
+        ON CONFLICT (GuildId, Source) DO UPDATE SET
+            Cursor = EXCLUDED.Cursor,
+            Updated = CURRENT_TIMESTAMP",
+        )
+        .bind(guild_id)
+        .bind(source)
+        .bind(cursor)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Called once a source has been read to the end, so the next run starts from the beginning.
+    pub async fn clear_backfill_checkpoint(
+        self,
+        guild_id: String,
+        source: String,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("clear_backfill_checkpoint");
+        sqlx::query("DELETE FROM backfill_checkpoints WHERE GuildId = $1 AND Source = $2")
+            .bind(guild_id)
+            .bind(source)
+            .execute(&self.db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns `None` if a job of this kind is already running in the guild.
+    pub async fn start_job(
+        self,
+        kind: String,
+        guild_id: String,
+        started_by: String,
+    ) -> Result<Option<i32>, anyhow::Error> {
+        let _timer = self.metrics.time_query("start_job");
+        let id = sqlx::query_scalar::<_, i32>(
+            "INSERT INTO jobs (Kind, GuildId, StartedBy) VALUES ($1, $2, $3)
+        ON CONFLICT (Kind, GuildId) WHERE Status = 'running' DO NOTHING
+        RETURNING Id",
+        )
+        .bind(kind)
+        .bind(guild_id)
+        .bind(started_by)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn update_job_progress(
+        self,
+        id: i32,
+        progress: JobProgress,
+    ) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("update_job_progress");
+        sqlx::query(
+            "UPDATE jobs SET
+            Stage = $2,
+            ThreadsChecked = $3,
+            IssuesChecked = $4,
+            RowsCreated = $5,
+            RowsUpdated = $6,
+            ThreadsLinked = $7,
+            ErrorCount = $8,
+            Errors = $9
+        WHERE Id = $1",
+        )
+        .bind(id)
+        .bind(progress.stage)
+        .bind(progress.threads_checked)
+        .bind(progress.issues_checked)
+        .bind(progress.rows_created)
+        .bind(progress.rows_updated)
+        .bind(progress.threads_linked)
+        .bind(progress.error_count)
+        .bind(progress.errors)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks the job as failed if there's a failure, or as succeeded otherwise.
+    pub async fn finish_job(self, id: i32, failure: Option<String>) -> Result<(), anyhow::Error> {
+        let _timer = self.metrics.time_query("finish_job");
+        sqlx::query(
+            "UPDATE jobs SET
+            Status = CASE WHEN $2::varchar IS NULL THEN 'succeeded' ELSE 'failed' END,
+            Failure = $2,
+            Finished = CURRENT_TIMESTAMP
+        WHERE Id = $1",
+        )
+        .bind(id)
+        .bind(failure)
+        .execute(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Jobs run inside the bot, so any still running at startup were cut off by a restart.
+    pub async fn fail_interrupted_jobs(self) -> Result<u64, anyhow::Error> {
+        let _timer = self.metrics.time_query("fail_interrupted_jobs");
+        let res = sqlx::query(
+            "UPDATE jobs SET
+            Status = 'failed',
+            Failure = 'The bot restarted while this job was running',
+            Finished = CURRENT_TIMESTAMP
+        WHERE Status = 'running'",
+        )
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    pub async fn get_job(self, id: i32) -> Result<Option<Job>, String> {
+        let _timer = self.metrics.time_query("get_job");
+        match sqlx::query_as::<_, Job>(&format!("{JOBS_QUERY} WHERE Id = $1"))
+            .bind(id)
+            .fetch_optional(&self.db)
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting job: {e}")),
+        }
+    }
+
+    pub async fn get_latest_job(
+        self,
+        kind: String,
+        guild_id: String,
+    ) -> Result<Option<Job>, String> {
+        let _timer = self.metrics.time_query("get_latest_job");
+        match sqlx::query_as::<_, Job>(&format!(
+            "{JOBS_QUERY} WHERE Kind = $1 AND GuildId = $2 ORDER BY Started DESC LIMIT 1"
+        ))
+        .bind(kind)
+        .bind(guild_id)
+        .fetch_optional(&self.db)
+        .await
+        {
+            Ok(res) => Ok(res),
+            Err(e) => Err(format!("Error occurred while getting the latest job: {e}")),
+        }
+    }
+
+    /// Every help thread in the guild the bot has a row for.
+    pub async fn reconcile_get_threads(
+        self,
+        guild_id: String,
+    ) -> Result<Vec<KnownThread>, anyhow::Error> {
+        let _timer = self.metrics.time_query("reconcile_get_threads");
+        let threads = sqlx::query_as::<_, KnownThread>(
+            "SELECT
+        DiscordThreadId as thread_id,
+        ThreadState as thread_state,
+        OriginalPosterId IS NOT NULL AND FirstResponseUserId IS NOT NULL as complete
+        FROM issues
+        WHERE GuildId = $1 AND DiscordThreadId IS NOT NULL",
+        )
+        .bind(guild_id)
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(threads)
+    }
+
+    /// Returns false if there's no row for the thread or it was already in that state.
+    pub async fn set_thread_state(
+        self,
+        thread_id: String,
+        state: ThreadState,
+    ) -> Result<bool, anyhow::Error> {
+        let _timer = self.metrics.time_query("set_thread_state");
+        let res = sqlx::query(
+            "UPDATE issues SET ThreadState = $2, LastUpdated = CURRENT_TIMESTAMP
+        WHERE DiscordThreadId = $1 AND ThreadState IS DISTINCT FROM $2",
+        )
+        .bind(thread_id)
+        .bind(state.as_str())
+        .execute(&self.db)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+}
+
+// These run against a fresh database with the migrations applied, so they need a Postgres server:
+// DATABASE_URL=postgres://... cargo test -- --ignored
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queries(db: PgPool) -> DBQueries {
+        DBQueries {
+            db,
+            metrics: Metrics::new().unwrap(),
+        }
+    }
+
+    /// Adds an issue, with each time given as an offset from the start of the current week in `timezone`.
+    async fn add_issue(
+        db: &PgPool,
+        guild: &str,
+        timezone: &str,
+        created: &str,
+        first_response: Option<(&str, &str)>,
+        resolved: Option<(&str, &str)>,
+    ) {
+        sqlx::query(
+            "WITH week AS (
+            SELECT date_trunc('week', CURRENT_TIMESTAMP AT TIME ZONE $2) AT TIME ZONE $2 as start
+        )
+        INSERT INTO issues (GuildId, Created, FirstResponseUser, FirstResponseTimedate, Resolved, ResolverUser, ResolvedTimedate)
+        SELECT $1, start + CAST($3 as interval), $4, start + CAST($5 as interval), $6 IS NOT NULL, $6, start + CAST($7 as interval)
+        FROM week",
+        )
+        .bind(guild)
+        .bind(timezone)
+        .bind(created)
+        .bind(first_response.map(|(user, _)| user))
+        .bind(first_response.map(|(_, at)| at))
+        .bind(resolved.map(|(user, _)| user))
+        .bind(resolved.map(|(_, at)| at))
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn weekly_stats_count_each_metric_in_its_own_week(db: PgPool) {
+        // created and first answered last week, resolved this week
+        add_issue(
+            &db,
+            "1",
+            "UTC",
+            "-6 days",
+            Some(("alice", "-5 days")),
+            Some(("bob", "1 hour")),
+        )
+        .await;
+        add_issue(&db, "1", "UTC", "1 hour", Some(("carol", "2 hours")), None).await;
+
+        let stats = queries(db)
+            .get_weekly_stats(2, "UTC".to_string(), Some("1".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(stats.len(), 2);
+
+        // most recent week first
+        let (this_week, last_week) = (&stats[0], &stats[1]);
+
+        assert_eq!(this_week.total_issues, 1);
+        assert_eq!(this_week.total_resolved_issues, 1);
+        assert_eq!(this_week.best_solver.as_deref(), Some("bob"));
+        assert_eq!(this_week.best_first_responder.as_deref(), Some("carol"));
+
+        assert_eq!(last_week.total_issues, 1);
+        assert_eq!(last_week.total_resolved_issues, 0);
+        assert_eq!(last_week.best_solver, None);
+        assert_eq!(last_week.best_first_responder.as_deref(), Some("alice"));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn issues_list_issues_without_a_thread_link(db: PgPool) {
+        // issues backfilled from GitHub don't have a thread
+        add_issue(&db, "1", "UTC", "1 hour", None, None).await;
+
+        let issues = queries(db)
+            .get_all_issues(IssueFilters {
+                assignee: None,
+                guild: Some("1".to_string()),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].discord_thread_link, None);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn weekly_stats_only_count_the_requested_guild(db: PgPool) {
+        add_issue(&db, "1", "UTC", "1 hour", None, Some(("bob", "2 hours"))).await;
+        add_issue(&db, "2", "UTC", "1 hour", None, Some(("dave", "2 hours"))).await;
+        add_issue(&db, "2", "UTC", "3 hours", None, Some(("dave", "4 hours"))).await;
+
+        let db = queries(db);
+
+        let guild = db
+            .clone()
+            .get_weekly_stats(1, "UTC".to_string(), Some("1".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(guild[0].total_issues, 1);
+        assert_eq!(guild[0].best_solver.as_deref(), Some("bob"));
+
+        let everything = db
+            .get_weekly_stats(1, "UTC".to_string(), None)
+            .await
+            .unwrap();
+        assert_eq!(everything[0].total_issues, 3);
+        assert_eq!(everything[0].best_solver.as_deref(), Some("dave"));
+        assert_eq!(everything[0].best_solver_count, Some(2));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn weekly_stats_start_weeks_in_the_given_timezone(db: PgPool) {
+        add_issue(&db, "1", "Pacific/Auckland", "-30 minutes", None, None).await;
+        add_issue(&db, "1", "Pacific/Auckland", "30 minutes", None, None).await;
+
+        let stats = queries(db)
+            .get_weekly_stats(2, "Pacific/Auckland".to_string(), Some("1".to_string()))
+            .await
+            .unwrap();
+
+        assert_eq!(stats[0].total_issues, 1);
+        assert_eq!(stats[1].total_issues, 1);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn sla_compliance_starts_weeks_in_the_given_timezone(db: PgPool) {
+        add_issue(&db, "1", "Pacific/Auckland", "-8 days", None, None).await;
+        add_issue(&db, "1", "Pacific/Auckland", "-30 minutes", None, None).await;
+        add_issue(&db, "1", "Pacific/Auckland", "30 minutes", None, None).await;
+
+        let stats = queries(db)
+            .get_sla_compliance_stats(2, "Pacific/Auckland".to_string(), Some("1".to_string()))
+            .await
+            .unwrap();
+
+        // the issue from before the first week is left out
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].total_issues, 1);
+        assert_eq!(stats[1].total_issues, 1);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn stats_series_only_count_the_requested_guild(db: PgPool) {
+        add_issue(&db, "1", "UTC", "1 hour", None, None).await;
+        add_issue(&db, "2", "UTC", "1 hour", None, None).await;
+
+        let today = Utc::now().date_naive();
+        let series = queries(db)
+            .get_stats_series(StatsQuery {
+                from: today - chrono::Duration::days(7),
+                to: today,
+                granularity: Some(Granularity::Week),
+                group_by: None,
+                guild: Some("1".to_string()),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(series.iter().map(|point| point.opened).sum::<i64>(), 1);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn leaderboard_only_ranks_the_requested_guild(db: PgPool) {
+        add_issue(&db, "1", "UTC", "1 hour", None, Some(("bob", "2 hours"))).await;
+        add_issue(&db, "2", "UTC", "1 hour", None, Some(("dave", "2 hours"))).await;
+
+        let leaderboard = queries(db)
+            .get_leaderboard("1".to_string(), LeaderboardPeriod::All, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(leaderboard.len(), 1);
+        assert_eq!(leaderboard[0].name, "bob");
+        assert_eq!(leaderboard[0].resolved_threads, 1);
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn contribution_counts_follow_users_across_renames(db: PgPool) {
+        for name in ["bob", "bobby"] {
+            sqlx::query(
+                "INSERT INTO issues (GuildId, Resolved, ResolverUser, ResolverUserId, ResolvedTimedate)
+            VALUES ('1', TRUE, $1, '42', CURRENT_TIMESTAMP)",
+            )
+            .bind(name)
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        let counts = queries(db)
+            .get_contribution_counts("1".to_string(), 30)
+            .await
+            .unwrap();
+
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].user_id, "42");
+        assert_eq!(counts[0].resolved_threads, 2);
+    }
 }
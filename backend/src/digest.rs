@@ -0,0 +1,152 @@
+use crate::database::{DBQueries, TopUser, WeeklyDigestStats};
+use crate::guilds::Guilds;
+use chrono::{DateTime, Datelike, Days, Utc};
+use poise::serenity_prelude::{CreateEmbed, GuildId, Http};
+use std::sync::Arc;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+/// Summary of a guild's last 7 days of support activity, posted to its staff channel every week.
+pub struct WeeklyDigest {
+    stats: WeeklyDigestStats,
+    top_solvers: Vec<TopUser>,
+}
+
+impl WeeklyDigest {
+    pub async fn fetch(db: DBQueries, guild_id: GuildId) -> Result<Self, String> {
+        let stats = db
+            .clone()
+            .get_weekly_digest_stats(guild_id.to_string())
+            .await?;
+        let top_solvers = db.get_top_solvers(guild_id.to_string(), 3).await?;
+
+        Ok(Self { stats, top_solvers })
+    }
+
+    pub fn render<'a>(&self, e: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        let top_solvers = if self.top_solvers.is_empty() {
+            "Nobody resolved a thread this week".to_string()
+        } else {
+            self.top_solvers
+                .iter()
+                .enumerate()
+                .map(|(idx, user)| format!("{}. {} ({})", idx + 1, user.name, user.count))
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        let total_votes = self.stats.upvotes + self.stats.downvotes;
+        let feedback = if total_votes == 0 {
+            "No feedback yet".to_string()
+        } else {
+            format!(
+                "{}% positive ({} 👍 / {} 👎)",
+                self.stats.upvotes * 100 / total_votes,
+                self.stats.upvotes,
+                self.stats.downvotes
+            )
+        };
+
+        e.title("Weekly support digest")
+            .description("Support activity over the last 7 days.")
+            .colour(0xFF8A3F)
+            .field("New issues", self.stats.new_issues, true)
+            .field("Resolved", self.stats.resolved_issues, true)
+            .field("Elevated to GitHub", self.stats.elevated_issues, true)
+            .field(
+                "Average first response time",
+                self.stats
+                    .average_response_time
+                    .clone()
+                    .unwrap_or_else(|| "No responses yet".to_string()),
+                true,
+            )
+            .field("Unresolved backlog", self.stats.unresolved_backlog, true)
+            .field("Feedback", feedback, true)
+            .field("Top solvers", top_solvers, false)
+            .timestamp(Utc::now())
+    }
+}
+
+/// Posts each guild's weekly digest to its staff channel every Monday at 09:00 UTC.
+/// Guilds without a staff channel are skipped.
+pub async fn post_weekly_digests(http: Arc<Http>, db: DBQueries, guilds: Guilds) {
+    loop {
+        sleep(duration_until_next_digest(Utc::now())).await;
+
+        for guild_id in guilds.ids().await {
+            let Some(channel_id) = guilds
+                .get(guild_id)
+                .await
+                .and_then(|guild| guild.staff_channel_id)
+            else {
+                continue;
+            };
+
+            let digest = match WeeklyDigest::fetch(db.clone(), guild_id).await {
+                Ok(res) => res,
+                Err(e) => {
+                    error!("Couldn't fetch weekly digest for {guild_id}: {e}");
+                    continue;
+                }
+            };
+
+            match channel_id
+                .send_message(&http, |m| m.embed(|e| digest.render(e)))
+                .await
+            {
+                Ok(_) => info!("Posted weekly digest to {channel_id}"),
+                Err(e) => error!("Couldn't post weekly digest for {guild_id}: {e}"),
+            }
+        }
+    }
+}
+
+fn duration_until_next_digest(now: DateTime<Utc>) -> std::time::Duration {
+    let days_until_monday = (7 - now.weekday().num_days_from_monday()) % 7;
+
+    // unwrap ok here as 09:00:00 is always a valid time
+    let mut next = DateTime::<Utc>::from_utc(
+        (now.date_naive() + Days::new(days_until_monday.into()))
+            .and_hms_opt(9, 0, 0)
+            .unwrap(),
+        Utc,
+    );
+
+    if next <= now {
+        next = next + Days::new(7);
+    }
+
+    (next - now).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        // 2023-07-17 was a Monday
+        Utc.with_ymd_and_hms(2023, 7, day, hour, min, 0).unwrap()
+    }
+
+    fn hours(hours: u64) -> std::time::Duration {
+        std::time::Duration::from_secs(hours * 60 * 60)
+    }
+
+    #[test]
+    fn waits_until_monday_morning() {
+        assert_eq!(duration_until_next_digest(at(17, 0, 0)), hours(9));
+        assert_eq!(duration_until_next_digest(at(21, 9, 0)), hours(3 * 24));
+        assert_eq!(duration_until_next_digest(at(23, 21, 0)), hours(12));
+    }
+
+    #[test]
+    fn waits_a_week_once_this_weeks_digest_is_due() {
+        assert_eq!(duration_until_next_digest(at(17, 9, 0)), hours(7 * 24));
+        assert_eq!(
+            duration_until_next_digest(at(17, 9, 30)),
+            hours(7 * 24) - std::time::Duration::from_secs(30 * 60)
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use axum::body::StreamBody;
+use futures::{stream::BoxStream, StreamExt};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::error;
+
+use crate::database::{DBQueries, Issue, IssueFilters, WeeklyStats};
+
+/// How many rendered rows can be waiting to be sent to the client before we stop reading from Postgres.
+const EXPORT_BUFFER_SIZE: usize = 64;
+
+pub type ExportBody = StreamBody<ReceiverStream<Result<String, sqlx::Error>>>;
+
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::JsonLines => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::JsonLines => "jsonl",
+        }
+    }
+}
+
+/// A row that can be written out as either a CSV record or a JSON line.
+pub trait ExportRow: Serialize {
+    const CSV_HEADER: &'static [&'static str];
+
+    fn csv_fields(&self) -> Vec<String>;
+}
+
+impl ExportRow for Issue {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "original_poster",
+        "discord_thread_link",
+        "severity",
+        "first_responder",
+        "github_link",
+        "resolved_by",
+        "assigned_to",
+        "categories",
+        "creation_date",
+        "first_response_breached",
+        "first_response_breach_duration",
+        "resolution_breached",
+        "resolution_breach_duration",
+    ];
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.original_poster.clone().unwrap_or_default(),
+            self.discord_thread_link.clone().unwrap_or_default(),
+            self.severity.to_string(),
+            self.first_responder.clone().unwrap_or_default(),
+            self.github_link.clone().unwrap_or_default(),
+            self.resolved_by.clone().unwrap_or_default(),
+            self.assigned_to.clone().unwrap_or_default(),
+            self.categories
+                .as_ref()
+                .map(|categories| categories.join(";"))
+                .unwrap_or_default(),
+            self.creation_date.clone(),
+            self.first_response_breached.to_string(),
+            self.first_response_breach_duration
+                .clone()
+                .unwrap_or_default(),
+            self.resolution_breached.to_string(),
+            self.resolution_breach_duration.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl ExportRow for WeeklyStats {
+    const CSV_HEADER: &'static [&'static str] = &[
+        "date_range",
+        "total_issues",
+        "total_elevated_issues",
+        "total_resolved_issues",
+        "total_one_touch_threads",
+        "extended_threads",
+        "average_response_time",
+        "best_solver",
+        "best_solver_count",
+        "best_first_responder",
+        "best_first_responder_count",
+    ];
+
+    fn csv_fields(&self) -> Vec<String> {
+        vec![
+            self.date_range.clone(),
+            self.total_issues.to_string(),
+            self.total_elevated_issues.to_string(),
+            self.total_resolved_issues.to_string(),
+            self.total_one_touch_threads.to_string(),
+            self.extended_threads.to_string(),
+            self.average_response_time.clone().unwrap_or_default(),
+            self.best_solver.clone().unwrap_or_default(),
+            self.best_solver_count
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+            self.best_first_responder.clone().unwrap_or_default(),
+            self.best_first_responder_count
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+/// Streams every issue matching the filters, in the same shape as the issues list.
+pub fn export_issues(db: DBQueries, filters: IssueFilters, format: ExportFormat) -> ExportBody {
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER_SIZE);
+
+    tokio::spawn(async move {
+        forward_rows(db.stream_issues(&filters), format, tx).await;
+    });
+
+    StreamBody::new(ReceiverStream::new(rx))
+}
+
+/// Streams the weekly stats shown on the dashboard.
+pub fn export_weekly_stats(
+    db: DBQueries,
+    weeks: i32,
+    timezone: String,
+    guild: Option<String>,
+    format: ExportFormat,
+) -> ExportBody {
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER_SIZE);
+
+    tokio::spawn(async move {
+        forward_rows(db.stream_weekly_stats(weeks, timezone, guild), format, tx).await;
+    });
+
+    StreamBody::new(ReceiverStream::new(rx))
+}
+
+/// Renders rows as they come out of Postgres and sends them on to the response body.
+/// Stops early if the client goes away or the query fails.
+async fn forward_rows<T: ExportRow>(
+    mut rows: BoxStream<'_, Result<T, sqlx::Error>>,
+    format: ExportFormat,
+    tx: mpsc::Sender<Result<String, sqlx::Error>>,
+) {
+    if let ExportFormat::Csv = format {
+        let header = csv_record(T::CSV_HEADER.iter().map(|field| field.to_string()));
+        if tx.send(Ok(header)).await.is_err() {
+            return;
+        }
+    }
+
+    while let Some(row) = rows.next().await {
+        let line = match row {
+            Ok(row) => match format {
+                ExportFormat::Csv => csv_record(row.csv_fields().into_iter()),
+                ExportFormat::JsonLines => match serde_json::to_string(&row) {
+                    Ok(json) => format!("{json}\n"),
+                    Err(e) => {
+                        error!("Error when serializing export row: {e}");
+                        continue;
+                    }
+                },
+            },
+            Err(e) => {
+                error!("Error when streaming export: {e}");
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+
+        if tx.send(Ok(line)).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn csv_record(fields: impl Iterator<Item = String>) -> String {
+    let mut record = fields
+        .map(|field| csv_field(&field))
+        .collect::<Vec<_>>()
+        .join(",");
+    record.push_str("\r\n");
+    record
+}
+
+/// Quotes a field if it contains anything that would otherwise break the record. Fields that a
+/// spreadsheet would read as a formula (usernames and first messages are user controlled) are
+/// prefixed with `'` so they're shown as text instead.
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{field}")
+    } else {
+        field.to_string()
+    };
+
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_leaves_plain_fields_alone() {
+        assert_eq!(csv_field("someone"), "someone");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("1 day 02:00:00"), "1 day 02:00:00");
+    }
+
+    #[test]
+    fn csv_field_quotes_fields_that_would_break_the_record() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_field_escapes_formulas() {
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(
+            csv_field("=HYPERLINK(\"http://example.com\",\"x\")"),
+            "\"'=HYPERLINK(\"\"http://example.com\"\",\"\"x\"\")\""
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{Context, DiscordBotData};
+use poise::serenity_prelude::{
+    self as serenity, ActionRowComponent, ButtonStyle, CacheHttp, InputTextStyle,
+    InteractionResponseType, MessageComponentInteraction, MessageId, ModalSubmitInteraction,
+    Reaction, ReactionType, UserId,
+};
+use tracing::error;
+
+const HELPFUL_ID: &str = "feedback:helpful";
+const NOT_HELPFUL_ID: &str = "feedback:not_helpful";
+const COMMENT_ID: &str = "feedback:comment";
+const MODAL_ID: &str = "feedback:modal";
+const RATING_ID: &str = "feedback:rating";
+const COMMENT_INPUT_ID: &str = "feedback:comment_input";
+
+const UPVOTE_EMOJI: &str = "👍";
+const DOWNVOTE_EMOJI: &str = "👎";
+
+/// Posts the feedback prompt in a resolved thread and records who the feedback will apply to.
+pub async fn send_prompt(
+    ctx: &Context<'_>,
+    responders: Vec<String>,
+) -> Result<(), serenity::Error> {
+    let resolved_message = ctx
+        .data()
+        .guilds
+        .settings_for(ctx.guild_id(), &ctx.data().settings)
+        .await
+        .resolved_message;
+
+    let prompt = ctx
+        .channel_id()
+        .send_message(ctx.http(), |m| {
+            m.content(resolved_message)
+            .components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.custom_id(HELPFUL_ID)
+                            .label("Helpful")
+                            .emoji(ReactionType::Unicode(UPVOTE_EMOJI.to_string()))
+                            .style(ButtonStyle::Success)
+                    })
+                    .create_button(|b| {
+                        b.custom_id(NOT_HELPFUL_ID)
+                            .label("Not helpful")
+                            .emoji(ReactionType::Unicode(DOWNVOTE_EMOJI.to_string()))
+                            .style(ButtonStyle::Danger)
+                    })
+                    .create_button(|b| {
+                        b.custom_id(COMMENT_ID)
+                            .label("Rate & comment")
+                            .emoji('📝')
+                            .style(ButtonStyle::Secondary)
+                    })
+                })
+            })
+        })
+        .await?;
+
+    if let Err(e) = ctx
+        .data()
+        .db
+        .clone()
+        .discord_create_feedback_record(
+            ctx.channel_id().to_string(),
+            prompt.id.to_string(),
+            responders,
+        )
+        .await
+    {
+        error!("Error when creating feedback record: {e}");
+    }
+
+    Ok(())
+}
+
+/// Handles feedback button presses. Returns false if the interaction wasn't for a feedback button.
+pub async fn handle_component(
+    ctx: &serenity::Context,
+    data: &DiscordBotData,
+    interaction: &MessageComponentInteraction,
+) -> Result<bool, serenity::Error> {
+    let helpful = match interaction.data.custom_id.as_str() {
+        HELPFUL_ID => true,
+        NOT_HELPFUL_ID => false,
+        COMMENT_ID => {
+            if !is_original_poster(ctx, interaction.channel_id, interaction.user.id).await {
+                respond_ephemeral(ctx, interaction, NOT_OP_MESSAGE).await?;
+                return Ok(true);
+            }
+
+            interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::Modal)
+                        .interaction_response_data(|d| {
+                            d.custom_id(MODAL_ID)
+                                .title("Thread feedback")
+                                .components(|c| {
+                                    c.create_action_row(|r| {
+                                        r.create_input_text(|t| {
+                                            t.custom_id(RATING_ID)
+                                                .label("Rating (1-5)")
+                                                .style(InputTextStyle::Short)
+                                                .min_length(1)
+                                                .max_length(1)
+                                                .required(true)
+                                        })
+                                    })
+                                    .create_action_row(|r| {
+                                        r.create_input_text(|t| {
+                                            t.custom_id(COMMENT_INPUT_ID)
+                                                .label("Comment")
+                                                .style(InputTextStyle::Paragraph)
+                                                .max_length(1000)
+                                                .required(false)
+                                        })
+                                    })
+                                })
+                        })
+                })
+                .await?;
+
+            return Ok(true);
+        }
+        _ => return Ok(false),
+    };
+
+    if !is_original_poster(ctx, interaction.channel_id, interaction.user.id).await {
+        respond_ephemeral(ctx, interaction, NOT_OP_MESSAGE).await?;
+        return Ok(true);
+    }
+
+    if let Err(e) = data
+        .db
+        .clone()
+        .discord_set_feedback_helpful(
+            interaction.channel_id.to_string(),
+            interaction.user.name.clone(),
+            helpful,
+        )
+        .await
+    {
+        error!("Error when saving feedback: {e}");
+        respond_ephemeral(
+            ctx,
+            interaction,
+            "Something went wrong saving your feedback.",
+        )
+        .await?;
+        return Ok(true);
+    }
+
+    respond_ephemeral(ctx, interaction, "Thanks for your feedback!").await?;
+
+    Ok(true)
+}
+
+/// Handles the feedback modal being submitted. Returns false if the interaction wasn't for the feedback modal.
+pub async fn handle_modal(
+    ctx: &serenity::Context,
+    data: &DiscordBotData,
+    interaction: &ModalSubmitInteraction,
+) -> Result<bool, serenity::Error> {
+    if interaction.data.custom_id != MODAL_ID {
+        return Ok(false);
+    }
+
+    let mut rating = None;
+    let mut comment = None;
+
+    for component in interaction
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+    {
+        if let ActionRowComponent::InputText(input) = component {
+            match input.custom_id.as_str() {
+                RATING_ID => rating = input.value.trim().parse::<i16>().ok(),
+                COMMENT_INPUT_ID if !input.value.trim().is_empty() => {
+                    comment = Some(input.value.trim().to_string())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let message = match rating {
+        Some(rating @ 1..=5) => {
+            match data
+                .db
+                .clone()
+                .discord_set_feedback_rating(
+                    interaction.channel_id.to_string(),
+                    interaction.user.name.clone(),
+                    rating,
+                    comment,
+                )
+                .await
+            {
+                Ok(_) => "Thanks for your feedback!",
+                Err(e) => {
+                    error!("Error when saving feedback rating: {e}");
+                    "Something went wrong saving your feedback."
+                }
+            }
+        }
+        _ => "Ratings need to be a number from 1 to 5.",
+    };
+
+    interaction
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| d.content(message).ephemeral(true))
+        })
+        .await?;
+
+    Ok(true)
+}
+
+/// Counts a 👍/👎 reaction on a feedback prompt as the original poster's vote.
+/// Reactions on other messages, other emoji and reactions from anyone else are ignored.
+pub async fn handle_reaction(
+    ctx: &serenity::Context,
+    data: &DiscordBotData,
+    reaction: &Reaction,
+    added: bool,
+) -> Result<(), serenity::Error> {
+    let helpful = match &reaction.emoji {
+        ReactionType::Unicode(emoji) if emoji == UPVOTE_EMOJI => true,
+        ReactionType::Unicode(emoji) if emoji == DOWNVOTE_EMOJI => false,
+        _ => return Ok(()),
+    };
+
+    let user_id = match reaction.user_id {
+        Some(user_id) => user_id,
+        None => return Ok(()),
+    };
+
+    let thread_id = match data
+        .db
+        .clone()
+        .discord_get_feedback_thread(reaction.message_id.to_string())
+        .await
+    {
+        Ok(Some(thread_id)) => thread_id,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            error!("Error when looking up feedback prompt: {e}");
+            return Ok(());
+        }
+    };
+
+    if !is_original_poster(ctx, reaction.channel_id, user_id).await {
+        return Ok(());
+    }
+
+    let res = if added {
+        let user = reaction.user(&ctx.http).await?;
+        data.db
+            .clone()
+            .discord_set_feedback_helpful(thread_id, user.name, helpful)
+            .await
+    } else {
+        data.db
+            .clone()
+            .discord_clear_feedback_helpful(thread_id, helpful)
+            .await
+    };
+
+    if let Err(e) = res {
+        error!("Error when saving feedback reaction: {e}");
+    }
+
+    Ok(())
+}
+
+const NOT_OP_MESSAGE: &str = "Only the person who opened this thread can leave feedback on it.";
+
+/// Checks whether the user opened the thread. The starter message of a forum thread has the same ID as the thread.
+async fn is_original_poster(
+    ctx: &serenity::Context,
+    channel_id: serenity::ChannelId,
+    user_id: UserId,
+) -> bool {
+    match channel_id.message(&ctx.http, MessageId(channel_id.0)).await {
+        Ok(message) => message.author.id == user_id,
+        Err(e) => {
+            error!("Couldn't get starter message for thread {channel_id}: {e}");
+            false
+        }
+    }
+}
+
+async fn respond_ephemeral(
+    ctx: &serenity::Context,
+    interaction: &MessageComponentInteraction,
+    message: &str,
+) -> Result<(), serenity::Error> {
+    interaction
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|d| d.content(message).ephemeral(true))
+        })
+        .await
+}
@@ -1,50 +1,439 @@
-use octocrab::models::InstallationToken;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::EncodingKey;
+use octocrab::models::{AppId, Installation, InstallationId, InstallationToken};
 use octocrab::params::apps::CreateInstallationAccessToken;
 use octocrab::Octocrab;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 
-pub struct Github;
+/// Installation tokens are refreshed this long before they expire, so a request never goes out
+/// with a token that lapses partway through.
+const TOKEN_REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// A GitHub repository, written as `owner/repo`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RepoRef {
+    pub owner: String,
+    pub name: String,
+}
+
+impl FromStr for RepoRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+                Ok(Self {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                })
+            }
+            _ => Err(format!("{s} should be written as owner/repo")),
+        }
+    }
+}
+
+impl fmt::Display for RepoRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// Which repository issues are elevated to. Help forums can be mapped to their own repositories,
+/// and anything without a mapping goes to the default repository. Guilds can only pick a repository
+/// of their own from `guild_repos`, so a server admin can't elevate into an arbitrary repository the
+/// app happens to be installed on.
+#[derive(Clone, Debug)]
+pub struct RepoMapping {
+    pub default_repo: RepoRef,
+    pub forum_repos: HashMap<u64, RepoRef>,
+    pub guild_repos: HashSet<RepoRef>,
+}
+
+impl RepoMapping {
+    /// Parses the forum mapping, written as `forum_channel_id=owner/repo` pairs separated by commas,
+    /// and the repositories guilds may choose from, written as `owner/repo` separated by commas.
+    pub fn parse(default_repo: &str, forum_repos: &str, guild_repos: &str) -> Result<Self, String> {
+        let default_repo = default_repo.parse::<RepoRef>()?;
+
+        let forum_repos = forum_repos
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (forum_id, repo) = pair.split_once('=').ok_or_else(|| {
+                    format!("{pair} should be written as forum_channel_id=owner/repo")
+                })?;
+                let forum_id = forum_id
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| format!("{forum_id} isn't a channel ID"))?;
+
+                Ok((forum_id, repo.parse::<RepoRef>()?))
+            })
+            .collect::<Result<HashMap<u64, RepoRef>, String>>()?;
+
+        let guild_repos = guild_repos
+            .split(',')
+            .map(str::trim)
+            .filter(|repo| !repo.is_empty())
+            .map(str::parse::<RepoRef>)
+            .collect::<Result<HashSet<RepoRef>, String>>()?;
+
+        Ok(Self {
+            default_repo,
+            forum_repos,
+            guild_repos,
+        })
+    }
+
+    /// Whether a guild may elevate its issues to this repository.
+    pub fn allows(&self, repo: &RepoRef) -> bool {
+        repo == &self.default_repo
+            || self.forum_repos.values().any(|forum_repo| forum_repo == repo)
+            || self.guild_repos.contains(repo)
+    }
+
+    /// Picks the repository for a thread: the forum's own repository if it has one, then the
+    /// guild's repository if it's allowed, then the default.
+    pub fn repo_for<'a>(
+        &'a self,
+        forum_id: Option<u64>,
+        guild_repo: Option<&'a RepoRef>,
+    ) -> &'a RepoRef {
+        forum_id
+            .and_then(|forum_id| self.forum_repos.get(&forum_id))
+            .or(guild_repo.filter(|repo| self.allows(repo)))
+            .unwrap_or(&self.default_repo)
+    }
+
+    fn repos(&self) -> impl Iterator<Item = &RepoRef> {
+        std::iter::once(&self.default_repo).chain(self.forum_repos.values())
+    }
+}
+
+/// A handle to the GitHub API that can be shared between the bot and the router.
+/// Use `client_for()` to get an `Octocrab` instance right before making a request.
+#[derive(Clone)]
+pub struct Github {
+    auth: Arc<GithubAuth>,
+    pub repos: Arc<RepoMapping>,
+}
+
+enum GithubAuth {
+    Personal(Octocrab),
+    App(AppAuth),
+}
+
+/// Installations are looked up the first time a repository is used, since guilds can add
+/// repositories while the bot is running.
+struct AppAuth {
+    app_id: AppId,
+    key: EncodingKey,
+    installations: RwLock<HashMap<InstallationId, Arc<InstallationAuth>>>,
+    repo_installations: RwLock<HashMap<RepoRef, InstallationId>>,
+}
+
+struct InstallationAuth {
+    access_tokens_url: String,
+    repositories: Vec<String>,
+    token: RwLock<Option<CachedToken>>,
+}
+
+struct CachedToken {
+    crab: Octocrab,
+    expires_at: DateTime<Utc>,
+}
+
+impl CachedToken {
+    fn expires_soon(&self) -> bool {
+        self.expires_at - Duration::minutes(TOKEN_REFRESH_MARGIN_MINUTES) <= Utc::now()
+    }
+}
 
 impl Github {
-    pub async fn init_as_app(app_id: String, rsa_key: String) -> Result<Octocrab, anyhow::Error> {
-        let key = jsonwebtoken::EncodingKey::from_rsa_pem(rsa_key.as_bytes()).unwrap();
-        let token =
-            octocrab::auth::create_jwt(app_id.parse::<u64>().unwrap().into(), &key).unwrap();
+    /// Looks up the app's installation for every configured repository. Repositories owned by
+    /// different accounts can use different installations.
+    pub async fn init_as_app(
+        app_id: u64,
+        key: EncodingKey,
+        repos: RepoMapping,
+    ) -> Result<Self, anyhow::Error> {
+        let github = Self {
+            auth: Arc::new(GithubAuth::App(AppAuth {
+                app_id: AppId::from(app_id),
+                key,
+                installations: RwLock::new(HashMap::new()),
+                repo_installations: RwLock::new(HashMap::new()),
+            })),
+            repos: Arc::new(repos),
+        };
 
-        let octocrab = Octocrab::builder().personal_token(token).build().unwrap();
+        // mint the first tokens now so a misconfigured app fails at startup rather than on first use
+        for repo in github.repos.repos() {
+            github.client_for(repo).await?;
+        }
 
-        let installations = octocrab
-            .apps()
-            .installations()
-            .send()
-            .await
-            .unwrap()
-            .take_items();
+        Ok(github)
+    }
 
-        let mut create_access_token = CreateInstallationAccessToken::default();
-        create_access_token.repositories = vec!["test".to_string()];
+    pub fn init_as_personal(token: String, repos: RepoMapping) -> Result<Self, anyhow::Error> {
+        let crab = octocrab::OctocrabBuilder::new()
+            .personal_token(token)
+            .build()?;
+
+        Ok(Self {
+            auth: Arc::new(GithubAuth::Personal(crab)),
+            repos: Arc::new(repos),
+        })
+    }
+
+    /// Returns a client for the default repository.
+    pub async fn client(&self) -> Result<Octocrab, anyhow::Error> {
+        self.client_for(&self.repos.default_repo).await
+    }
+
+    /// Returns a client that can access the repository, refreshing its installation token first if it's about to expire.
+    pub async fn client_for(&self, repo: &RepoRef) -> Result<Octocrab, anyhow::Error> {
+        match self.auth.as_ref() {
+            GithubAuth::Personal(crab) => Ok(crab.clone()),
+            GithubAuth::App(app) => app.client_for(repo).await,
+        }
+    }
+
+    /// When the next installation token expires. Personal tokens don't report an expiry.
+    pub async fn token_expiry(&self) -> Option<DateTime<Utc>> {
+        match self.auth.as_ref() {
+            GithubAuth::Personal(_) => None,
+            GithubAuth::App(app) => {
+                let mut expiry = None;
+                for installation in app.installations.read().await.values() {
+                    if let Some(token) = installation.token.read().await.as_ref() {
+                        expiry = Some(expiry.map_or(token.expires_at, |expiry: DateTime<Utc>| {
+                            expiry.min(token.expires_at)
+                        }));
+                    }
+                }
+                expiry
+            }
+        }
+    }
+}
 
-        let access: InstallationToken = octocrab
-            .post(
-                installations[0].access_tokens_url.as_ref().unwrap(),
-                Some(&create_access_token),
+impl AppAuth {
+    async fn client_for(&self, repo: &RepoRef) -> Result<Octocrab, anyhow::Error> {
+        let installation = self.installation_for(repo).await?;
+
+        if let Some(token) = installation.token.read().await.as_ref() {
+            if !token.expires_soon() {
+                return Ok(token.crab.clone());
+            }
+        }
+
+        let mut token = installation.token.write().await;
+
+        // another request may have refreshed the token while we were waiting for the lock
+        if let Some(token) = token.as_ref() {
+            if !token.expires_soon() {
+                return Ok(token.crab.clone());
+            }
+        }
+
+        let refreshed = self.mint_token(&installation).await?;
+        let crab = refreshed.crab.clone();
+        *token = Some(refreshed);
+
+        Ok(crab)
+    }
+
+    async fn installation_for(
+        &self,
+        repo: &RepoRef,
+    ) -> Result<Arc<InstallationAuth>, anyhow::Error> {
+        // the locks are taken one at a time so this can't deadlock with a lookup below
+        let installation_id = self.repo_installations.read().await.get(repo).copied();
+        if let Some(installation_id) = installation_id {
+            if let Some(installation) = self.installations.read().await.get(&installation_id) {
+                return Ok(installation.clone());
+            }
+        }
+
+        let installation: Installation = app_client(self.app_id, &self.key)?
+            .get(
+                format!("/repos/{}/{}/installation", repo.owner, repo.name),
+                None::<&()>,
             )
             .await
-            .unwrap();
+            .with_context(|| format!("The GitHub app isn't installed on {repo}"))?;
+
+        let access_tokens_url = installation
+            .access_tokens_url
+            .ok_or_else(|| anyhow!("GitHub didn't return an access token URL for {repo}"))?;
+
+        let auth = {
+            let mut installations = self.installations.write().await;
+
+            let mut repositories = installations
+                .get(&installation.id)
+                .map(|existing| existing.repositories.clone())
+                .unwrap_or_default();
+            if !repositories.contains(&repo.name) {
+                repositories.push(repo.name.clone());
+            }
+
+            // tokens are scoped to the installation's repositories, so adding one means minting a new token
+            let auth = Arc::new(InstallationAuth {
+                access_tokens_url,
+                repositories,
+                token: RwLock::new(None),
+            });
+            installations.insert(installation.id, auth.clone());
+            auth
+        };
+
+        self.repo_installations
+            .write()
+            .await
+            .insert(repo.clone(), installation.id);
+
+        Ok(auth)
+    }
+
+    async fn mint_token(
+        &self,
+        installation: &InstallationAuth,
+    ) -> Result<CachedToken, anyhow::Error> {
+        let mut create_access_token = CreateInstallationAccessToken::default();
+        create_access_token.repositories = installation.repositories.clone();
+
+        let access: InstallationToken = app_client(self.app_id, &self.key)?
+            .post(&installation.access_tokens_url, Some(&create_access_token))
+            .await?;
+
+        // installation tokens last an hour - assume that if GitHub doesn't tell us otherwise
+        let expires_at = access
+            .expires_at
+            .as_deref()
+            .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
+            .map(|expires_at| expires_at.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc::now() + Duration::hours(1));
 
         let crab = octocrab::OctocrabBuilder::new()
             .personal_token(access.token)
-            .build()
-            .expect("Failed to build Octocrab instance");
+            .build()?;
 
-        Ok(crab)
+        Ok(CachedToken { crab, expires_at })
     }
+}
 
-    pub fn init_as_personal(token: String) -> Result<Octocrab, anyhow::Error> {
-        let crab = octocrab::OctocrabBuilder::new()
-            .personal_token(token)
-            .build()
-            .expect("Failed to build Octocrab instance");
+/// App JWTs are only valid for 10 minutes, so a fresh one is made for every request that needs it.
+fn app_client(app_id: AppId, key: &EncodingKey) -> Result<Octocrab, anyhow::Error> {
+    let jwt = octocrab::auth::create_jwt(app_id, key)?;
 
-        Ok(crab)
+    Ok(Octocrab::builder().personal_token(jwt).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo(owner: &str, name: &str) -> RepoRef {
+        RepoRef {
+            owner: owner.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn repo_ref_parses_owner_and_name() {
+        assert_eq!("shuttle-hq/shuttle".parse(), Ok(repo("shuttle-hq", "shuttle")));
+        assert_eq!(" shuttle-hq/shuttle ".parse(), Ok(repo("shuttle-hq", "shuttle")));
+        assert_eq!(repo("shuttle-hq", "shuttle").to_string(), "shuttle-hq/shuttle");
+    }
+
+    #[test]
+    fn repo_ref_rejects_anything_else() {
+        for s in ["", "shuttle", "/shuttle", "shuttle-hq/", "shuttle-hq/shuttle/issues"] {
+            assert!(s.parse::<RepoRef>().is_err(), "{s} shouldn't parse");
+        }
+    }
+
+    #[test]
+    fn repo_mapping_parses_forum_repos() {
+        let mapping = RepoMapping::parse(
+            "shuttle-hq/shuttle",
+            "1001=shuttle-hq/cch, 1002 = shuttle-hq/examples,",
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(mapping.default_repo, repo("shuttle-hq", "shuttle"));
+        assert_eq!(mapping.forum_repos.len(), 2);
+        assert_eq!(mapping.forum_repos[&1001], repo("shuttle-hq", "cch"));
+        assert_eq!(mapping.forum_repos[&1002], repo("shuttle-hq", "examples"));
+    }
+
+    #[test]
+    fn repo_mapping_allows_no_forum_repos() {
+        let mapping = RepoMapping::parse("shuttle-hq/shuttle", "", "").unwrap();
+
+        assert!(mapping.forum_repos.is_empty());
+        assert!(mapping.guild_repos.is_empty());
+    }
+
+    #[test]
+    fn repo_mapping_rejects_invalid_pairs() {
+        assert!(RepoMapping::parse("shuttle", "", "").is_err());
+        assert!(RepoMapping::parse("shuttle-hq/shuttle", "1001", "").is_err());
+        assert!(RepoMapping::parse("shuttle-hq/shuttle", "forum=shuttle-hq/cch", "").is_err());
+        assert!(RepoMapping::parse("shuttle-hq/shuttle", "1001=cch", "").is_err());
+        assert!(RepoMapping::parse("shuttle-hq/shuttle", "", "cch").is_err());
+    }
+
+    #[test]
+    fn repo_for_prefers_the_forum_then_the_guild() {
+        let mapping = RepoMapping::parse(
+            "shuttle-hq/shuttle",
+            "1001=shuttle-hq/cch",
+            "someone/guild-repo",
+        )
+        .unwrap();
+        let guild_repo = repo("someone", "guild-repo");
+
+        assert_eq!(mapping.repo_for(Some(1001), Some(&guild_repo)), &repo("shuttle-hq", "cch"));
+        assert_eq!(mapping.repo_for(Some(1002), Some(&guild_repo)), &guild_repo);
+        assert_eq!(mapping.repo_for(None, None), &repo("shuttle-hq", "shuttle"));
+    }
+
+    #[test]
+    fn repo_mapping_only_allows_configured_repos() {
+        let mapping = RepoMapping::parse(
+            "shuttle-hq/shuttle",
+            "1001=shuttle-hq/cch",
+            "someone/guild-repo, someone/other-repo",
+        )
+        .unwrap();
+
+        assert_eq!(mapping.guild_repos.len(), 2);
+        for allowed in [
+            repo("shuttle-hq", "shuttle"),
+            repo("shuttle-hq", "cch"),
+            repo("someone", "guild-repo"),
+        ] {
+            assert!(mapping.allows(&allowed), "{allowed} should be allowed");
+        }
+        assert!(!mapping.allows(&repo("someone", "private-repo")));
+    }
+
+    #[test]
+    fn repo_for_ignores_a_guild_repo_that_isnt_allowed() {
+        let mapping = RepoMapping::parse("shuttle-hq/shuttle", "", "").unwrap();
+        let guild_repo = repo("someone", "private-repo");
+
+        assert_eq!(mapping.repo_for(None, Some(&guild_repo)), &repo("shuttle-hq", "shuttle"));
     }
 }
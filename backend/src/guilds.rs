@@ -0,0 +1,185 @@
+use poise::serenity_prelude::{ChannelId, GuildId, RoleId};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::database::{DBQueries, GuildRecord, StaffRoleRecord};
+use crate::github::RepoRef;
+use crate::permissions::StaffTier;
+use crate::settings::{Settings, SharedSettings};
+
+/// How one guild is set up, from its row in the `guilds` table.
+#[derive(Clone)]
+pub struct GuildConfig {
+    pub guild_id: GuildId,
+    pub name: String,
+    pub staff_roles: HashMap<RoleId, StaffTier>,
+    pub help_forum_ids: Vec<ChannelId>,
+    pub github_repo: Option<RepoRef>,
+    /// Where the weekly digest is posted. Without one, the guild doesn't get a digest.
+    pub staff_channel_id: Option<ChannelId>,
+    /// GitHub logins that can view this guild on the dashboard.
+    pub dashboard_users: Vec<String>,
+    /// The global settings with this guild's overrides applied.
+    pub settings: Settings,
+}
+
+impl GuildConfig {
+    fn from_record(
+        record: GuildRecord,
+        staff_roles: Vec<StaffRoleRecord>,
+        global: &Settings,
+    ) -> Result<Self, String> {
+        let guild_id = record.guild_id;
+        let parse_id = |id: &str| {
+            id.parse::<u64>()
+                .map_err(|_| format!("Guild {guild_id} has an invalid ID: {id}"))
+        };
+
+        let settings = global
+            .with_overrides(record.settings.0)
+            .map_err(|e| format!("Guild {guild_id} has an invalid setting: {e}"))?;
+
+        // guilds that haven't picked their forums yet watch the one from the settings
+        let help_forum_ids = if record.help_forum_ids.is_empty() {
+            vec![ChannelId(settings.help_forum_id)]
+        } else {
+            record
+                .help_forum_ids
+                .iter()
+                .map(|id| parse_id(id).map(ChannelId))
+                .collect::<Result<Vec<ChannelId>, String>>()?
+        };
+
+        let staff_roles = staff_roles
+            .into_iter()
+            .map(|role| {
+                Ok((
+                    RoleId(parse_id(&role.role_id)?),
+                    StaffTier::parse(&role.tier)
+                        .map_err(|e| format!("Guild {guild_id} has an invalid staff role: {e}"))?,
+                ))
+            })
+            .collect::<Result<HashMap<RoleId, StaffTier>, String>>()?;
+
+        let github_repo = match record.github_repo {
+            Some(repo) => Some(
+                repo.parse::<RepoRef>()
+                    .map_err(|e| format!("Guild {guild_id} has an invalid GitHub repo: {e}"))?,
+            ),
+            None => None,
+        };
+
+        let staff_channel_id = match record.staff_channel_id {
+            Some(id) => Some(ChannelId(parse_id(&id)?)),
+            None => None,
+        };
+
+        Ok(Self {
+            guild_id: GuildId(parse_id(&guild_id)?),
+            staff_roles,
+            name: record.name,
+            help_forum_ids,
+            github_repo,
+            staff_channel_id,
+            dashboard_users: record.dashboard_users,
+            settings,
+        })
+    }
+
+    /// Whether threads in this channel are help threads.
+    pub fn watches(&self, forum_id: Option<ChannelId>) -> bool {
+        forum_id.is_some_and(|forum_id| self.help_forum_ids.contains(&forum_id))
+    }
+
+    /// The highest tier any of the roles give.
+    pub fn tier_for(&self, roles: &[RoleId]) -> Option<StaffTier> {
+        roles
+            .iter()
+            .filter_map(|role| self.staff_roles.get(role))
+            .copied()
+            .max()
+    }
+
+    pub fn allows_dashboard_user(&self, github_login: &str) -> bool {
+        self.dashboard_users
+            .iter()
+            .any(|user| user.eq_ignore_ascii_case(github_login))
+    }
+}
+
+/// Every guild the bot has been set up in. Shared between the bot and the router, and reloaded
+/// whenever a guild or the global settings change.
+#[derive(Clone, Default)]
+pub struct Guilds(Arc<RwLock<HashMap<GuildId, GuildConfig>>>);
+
+impl Guilds {
+    pub async fn load(db: DBQueries, global: &Settings) -> Result<Self, String> {
+        let guilds = Self::default();
+        guilds.reload(db, global).await?;
+        Ok(guilds)
+    }
+
+    /// Replaces every guild's config. If any of them are invalid, the old configs are kept.
+    pub async fn reload(&self, db: DBQueries, global: &Settings) -> Result<(), String> {
+        let mut staff_roles: HashMap<String, Vec<StaffRoleRecord>> = HashMap::new();
+        for role in db.clone().get_staff_roles().await? {
+            staff_roles
+                .entry(role.guild_id.clone())
+                .or_default()
+                .push(role);
+        }
+
+        let guilds = db
+            .get_guilds()
+            .await?
+            .into_iter()
+            .map(|record| {
+                let roles = staff_roles.remove(&record.guild_id).unwrap_or_default();
+                let guild = GuildConfig::from_record(record, roles, global)?;
+                Ok((guild.guild_id, guild))
+            })
+            .collect::<Result<HashMap<GuildId, GuildConfig>, String>>()?;
+
+        *self.0.write().await = guilds;
+
+        Ok(())
+    }
+
+    pub async fn get(&self, guild_id: GuildId) -> Option<GuildConfig> {
+        self.0.read().await.get(&guild_id).cloned()
+    }
+
+    pub async fn ids(&self) -> Vec<GuildId> {
+        self.0.read().await.keys().copied().collect()
+    }
+
+    /// The guild's settings, or the global settings outside of a configured guild.
+    pub async fn settings_for(
+        &self,
+        guild_id: Option<GuildId>,
+        global: &SharedSettings,
+    ) -> Settings {
+        match guild_id {
+            Some(guild_id) => match self.get(guild_id).await {
+                Some(guild) => guild.settings,
+                None => global.read().await.clone(),
+            },
+            None => global.read().await.clone(),
+        }
+    }
+
+    /// Guilds the GitHub user can view on the dashboard.
+    pub async fn for_dashboard_user(&self, github_login: &str) -> Vec<GuildConfig> {
+        let mut guilds = self
+            .0
+            .read()
+            .await
+            .values()
+            .filter(|guild| guild.allows_dashboard_user(github_login))
+            .cloned()
+            .collect::<Vec<GuildConfig>>();
+        guilds.sort_by(|a, b| a.name.cmp(&b.name));
+        guilds
+    }
+}
@@ -0,0 +1,178 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::ShardManager;
+use serenity::gateway::ConnectionStage;
+use serde::Serialize;
+use shuttle_persist::PersistInstance;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+use crate::router::AppState;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+pub struct DependencyStatus {
+    pub status: Status,
+    #[serde(
+        rename(serialize = "latencyMs"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub latency_ms: Option<u128>,
+    #[serde(
+        rename(serialize = "expiresAt"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyStatus {
+    fn up(latency_ms: Option<u128>) -> Self {
+        Self {
+            status: Status::Up,
+            latency_ms,
+            expires_at: None,
+            error: None,
+        }
+    }
+
+    fn down(error: String) -> Self {
+        Self {
+            status: Status::Down,
+            latency_ms: None,
+            expires_at: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadinessReport {
+    pub status: Status,
+    pub database: DependencyStatus,
+    pub discord: DependencyStatus,
+    pub github: DependencyStatus,
+    pub persist: DependencyStatus,
+}
+
+/// The service is up as long as it can answer requests at all.
+pub async fn live() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({ "status": Status::Up })),
+    )
+}
+
+/// Checks every dependency the service needs, returning 503 if any of them are down.
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let (database, discord, github, persist) = tokio::join!(
+        check_database(&state),
+        check_discord(&state.shard_manager),
+        check_github(&state),
+        check_persist(state.persist.clone()),
+    );
+
+    let status = if [&database, &discord, &github, &persist]
+        .iter()
+        .all(|dependency| dependency.status == Status::Up)
+    {
+        Status::Up
+    } else {
+        Status::Down
+    };
+
+    let status_code = match status {
+        Status::Up => StatusCode::OK,
+        Status::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    (
+        status_code,
+        Json(ReadinessReport {
+            status,
+            database,
+            discord,
+            github,
+            persist,
+        }),
+    )
+}
+
+async fn check_database(state: &AppState) -> DependencyStatus {
+    let start = Instant::now();
+
+    match state.db.clone().ping().await {
+        Ok(_) => DependencyStatus::up(Some(start.elapsed().as_millis())),
+        Err(e) => DependencyStatus::down(e),
+    }
+}
+
+/// The gateway is up if every shard is connected. The reported latency is the slowest shard's heartbeat.
+async fn check_discord(shard_manager: &Arc<Mutex<ShardManager>>) -> DependencyStatus {
+    let shard_manager = shard_manager.lock().await;
+    let runners = shard_manager.runners.lock().await;
+
+    if runners.is_empty() {
+        return DependencyStatus::down("No shards are running".to_string());
+    }
+
+    if let Some((id, runner)) = runners
+        .iter()
+        .find(|(_, runner)| runner.stage != ConnectionStage::Connected)
+    {
+        return DependencyStatus::down(format!("Shard {} is {}", id.0, runner.stage));
+    }
+
+    let latency = runners
+        .values()
+        .filter_map(|runner| runner.latency)
+        .max()
+        .map(|latency| latency.as_millis());
+
+    DependencyStatus::up(latency)
+}
+
+/// The rate limit endpoint doesn't count against the rate limit, so it's a cheap way to check the token works.
+/// For GitHub apps, this also reports when the current installation token expires.
+async fn check_github(state: &AppState) -> DependencyStatus {
+    let start = Instant::now();
+
+    let client = match state.crab.client().await {
+        Ok(res) => res,
+        Err(e) => return DependencyStatus::down(format!("Couldn't get a GitHub token: {e}")),
+    };
+
+    let res = client.ratelimit().get().await;
+    state.db.metrics.record_github_call("rate_limit", &res);
+
+    match res {
+        Ok(_) => DependencyStatus {
+            expires_at: state.crab.token_expiry().await,
+            ..DependencyStatus::up(Some(start.elapsed().as_millis()))
+        },
+        Err(e) => DependencyStatus::down(e.to_string()),
+    }
+}
+
+async fn check_persist(persist: PersistInstance) -> DependencyStatus {
+    let start = Instant::now();
+    let now = Utc::now();
+
+    if let Err(e) = persist.save::<DateTime<Utc>>("healthcheck", now) {
+        return DependencyStatus::down(e.to_string());
+    }
+
+    match persist.load::<DateTime<Utc>>("healthcheck") {
+        Ok(res) if res == now => DependencyStatus::up(Some(start.elapsed().as_millis())),
+        Ok(_) => DependencyStatus::down("Persist store returned a stale value".to_string()),
+        Err(e) => DependencyStatus::down(e.to_string()),
+    }
+}
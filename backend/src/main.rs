@@ -1,40 +1,64 @@
-use octocrab::Octocrab;
+use poise::serenity_prelude::Http;
 use shuttle_persist::PersistInstance;
 use shuttle_secrets::SecretStore;
 use sqlx::PgPool;
 use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
 use tokio::time::{sleep, Duration};
+mod backfill;
 mod bot;
 mod commands;
+mod config;
 mod database;
+mod digest;
+mod export;
+mod feedback;
 mod github;
+mod guilds;
+mod health;
+mod metrics;
 mod oauth;
+mod permissions;
 mod persist;
+mod reconcile;
+mod recognition;
 mod router;
+mod settings;
+mod severity;
 mod utils;
 
 use bot::{init_discord_bot};
-use database::DBQueries;
+use config::{Config, GithubAuthConfig, OAuthCredentials};
+use database::{AssignmentStrategy, DBQueries};
+use digest::post_weekly_digests;
 use github::Github;
+use guilds::Guilds;
+use metrics::{update_gauges_periodically, Metrics};
 use persist::Persist;
+use reconcile::reconcile_periodically;
+use recognition::sync_recognition_roles_periodically;
 use router::init_router;
-use utils::get_secrets;
+use settings::{Settings, SharedSettings};
 
 pub struct DiscordBotData {
     db: DBQueries,
-    crab: Octocrab,
-    staff_role_id: String,
-    server_id: String,
+    crab: Github,
+    guilds: Guilds,
+    auto_assign_strategy: Option<AssignmentStrategy>,
+    settings: SharedSettings,
 }
 
 struct CustomService {
     db: DBQueries,
     bot: Bot,
     public: PathBuf,
-    oauth_id: String,
-    oauth_secret: String,
+    oauth: Option<OAuthCredentials>,
     persist: PersistInstance,
-    crab: Octocrab,
+    crab: Github,
+    http: Arc<Http>,
+    settings: SharedSettings,
+    guilds: Guilds,
 }
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -42,7 +66,7 @@ type Context<'a> = poise::Context<'a, DiscordBotData, Error>;
 
 type Bot = poise::FrameworkBuilder<
     DiscordBotData,
-    Box<(dyn std::error::Error + std::marker::Send + Sync + 'static)>,
+    Box<dyn std::error::Error + std::marker::Send + Sync + 'static>,
 >;
 
 #[shuttle_runtime::main]
@@ -52,68 +76,117 @@ async fn custom(
     #[shuttle_static_folder::StaticFolder(folder = "public")] public: PathBuf,
     #[shuttle_persist::Persist] persist: PersistInstance,
 ) -> Result<CustomService, shuttle_runtime::Error> {
+    // check the config before doing anything else so that every problem with it is reported at once
+    let config = Config::from_secrets(&secret_store).map_err(anyhow::Error::from)?;
+    config.log_summary();
+
     sqlx::migrate!()
         .run(&db)
         .await
-        .expect("Found an error while running migrations");
+        .map_err(|e| anyhow::anyhow!("Found an error while running migrations: {e}"))?;
 
-    let db = DBQueries { db };
+    let metrics = Metrics::new().map_err(anyhow::Error::from)?;
 
-    // Get the discord token set in `Secrets.toml`
-    // unwrap ok here due to it being required
-    let secrets = get_secrets(secret_store).unwrap();
+    let db = DBQueries { db, metrics };
 
-    // set up octocrab instance
-    // if the PEM key and app ID exist, initiate as app - otherwise, initiate using personal key
-    // unwrap ok here due to this being required
-    let crab = if secrets.github_app_id != *"None" && secrets.github_app_pem_key != *"None" {
-        Github::init_as_app(secrets.github_app_id, secrets.github_app_pem_key)
+    // non-secret settings: defaults from settings.rs, overridden by the `settings` table
+    let settings: SharedSettings = Arc::new(RwLock::new(
+        Settings::load(db.clone())
             .await
-            .unwrap()
-    } else {
-        Github::init_as_personal(secrets.github_personal_token).unwrap()
+            .map_err(|e| anyhow::anyhow!("Couldn't load settings: {e}"))?,
+    ));
+
+    // the guild from the secrets is added on first run, and after that is configured like any other guild
+    db.clone()
+        .seed_guild(
+            config.discord_server_id.to_string(),
+            config.discord_server_staff_role_id.to_string(),
+            config.staff_channel_id.map(|id| id.to_string()),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Couldn't add the configured guild: {e}"))?;
+
+    db.clone()
+        .claim_unscoped_rows(config.discord_server_id.to_string())
+        .await
+        .map_err(|e| anyhow::anyhow!("Couldn't claim rows from before guilds existed: {e}"))?;
+
+    // so that a refresh cut off by a restart doesn't block the next one
+    db.clone()
+        .fail_interrupted_jobs()
+        .await
+        .map_err(|e| anyhow::anyhow!("Couldn't clean up interrupted jobs: {e}"))?;
+
+    let guilds = Guilds::load(db.clone(), &*settings.read().await)
+        .await
+        .map_err(|e| anyhow::anyhow!("Couldn't load guilds: {e}"))?;
+
+    // set up the GitHub client
+    // as an app, installation tokens are refreshed as they expire
+    let crab = match config.github_auth {
+        GithubAuthConfig::App { app_id, key } => {
+            Github::init_as_app(app_id, key, config.github_repos)
+                .await
+                .map_err(|e| anyhow::anyhow!("Couldn't set up the GitHub app: {e:#}"))?
+        }
+        GithubAuthConfig::PersonalToken(token) => {
+            Github::init_as_personal(token, config.github_repos)?
+        }
     };
 
-    // unwrap ok here as the discord bot is required for the service to run
+    // used for scheduled jobs that post to Discord outside of the bot's event handler
+    let http = Arc::new(Http::new(&config.discord_token));
+
     let bot = init_discord_bot(
-        &secrets.discord_token,
+        &config.discord_token,
         db.clone(),
         crab.clone(),
-        secrets.discord_server_staff_role_id,
-        secrets.discord_server_id,
+        guilds.clone(),
+        config.auto_assign_strategy,
+        settings.clone(),
     )
-    .await
-    .unwrap();
+    .await?;
 
     Ok(CustomService {
         db,
         bot,
         public,
-        oauth_id: secrets.oauth_id,
-        oauth_secret: secrets.oauth_secret,
+        oauth: config.oauth,
         persist,
         crab,
+        http,
+        settings,
+        guilds,
     })
 }
 
 #[shuttle_runtime::async_trait]
 impl shuttle_runtime::Service for CustomService {
     async fn bind(mut self, addr: std::net::SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        // the framework is built up front so the router can report on the gateway connection
+        let framework = self.bot.build().await.map_err(anyhow::Error::from)?;
+
         let router = init_router(
             self.public,
-            self.db,
-            self.oauth_id,
-            self.oauth_secret,
+            self.db.clone(),
+            self.oauth,
             self.persist.clone(),
-            self.crab,
+            self.crab.clone(),
+            framework.shard_manager().clone(),
+            self.settings.clone(),
+            self.guilds.clone(),
         );
 
         let serve_router = axum::Server::bind(&addr).serve(router.into_make_service());
 
         tokio::select! {
-            _ = self.bot.run() => {},
+            _ = framework.start() => {},
             _ = serve_router => {},
-            _ = remove_expired_sessions(self.persist) => {}
+            _ = remove_expired_sessions(self.persist) => {},
+            _ = post_weekly_digests(self.http.clone(), self.db.clone(), self.guilds.clone()) => {},
+            _ = reconcile_periodically(self.http.clone(), self.db.clone(), self.guilds.clone(), self.settings.clone()) => {},
+            _ = sync_recognition_roles_periodically(self.http, self.db.clone(), self.guilds, self.settings) => {},
+            _ = update_gauges_periodically(self.db, self.crab) => {}
         };
 
         Ok(())
@@ -0,0 +1,198 @@
+use axum::{
+    extract::{MatchedPath, State},
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    HistogramOpts, HistogramTimer, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder, TEXT_FORMAT,
+};
+use std::time::Instant;
+use tokio::time::{sleep, Duration};
+use tracing::error;
+
+use crate::database::DBQueries;
+use crate::github::Github;
+use crate::router::AppState;
+
+/// All of the Prometheus metrics for the bot and the API, registered against one registry
+/// so they can be rendered together on `/metrics`.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub discord_events: IntCounterVec,
+    pub command_invocations: IntCounterVec,
+    pub command_failures: IntCounterVec,
+    pub github_api_calls: IntCounterVec,
+    pub github_rate_limit_remaining: IntGauge,
+    pub db_query_duration: HistogramVec,
+    pub http_request_duration: HistogramVec,
+    pub open_threads: IntGauge,
+    pub unanswered_threads: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("shuttlebot".to_string()), None)?;
+
+        let discord_events = IntCounterVec::new(
+            Opts::new(
+                "discord_events_total",
+                "Discord events handled, by event type",
+            ),
+            &["event"],
+        )?;
+        let command_invocations = IntCounterVec::new(
+            Opts::new(
+                "command_invocations_total",
+                "Slash command invocations, by command",
+            ),
+            &["command"],
+        )?;
+        let command_failures = IntCounterVec::new(
+            Opts::new(
+                "command_failures_total",
+                "Slash commands that returned an error, by command",
+            ),
+            &["command"],
+        )?;
+        let github_api_calls = IntCounterVec::new(
+            Opts::new(
+                "github_api_calls_total",
+                "GitHub API calls, by endpoint and outcome",
+            ),
+            &["endpoint", "outcome"],
+        )?;
+        let github_rate_limit_remaining = IntGauge::new(
+            "github_rate_limit_remaining",
+            "Requests left in the current GitHub core rate limit window",
+        )?;
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_query_duration_seconds",
+                "Database query latency, by query",
+            ),
+            &["query"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency, by route",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let open_threads =
+            IntGauge::new("open_threads", "Help threads that haven't been resolved")?;
+        let unanswered_threads = IntGauge::new(
+            "unanswered_threads",
+            "Help threads that haven't had a first response",
+        )?;
+
+        registry.register(Box::new(discord_events.clone()))?;
+        registry.register(Box::new(command_invocations.clone()))?;
+        registry.register(Box::new(command_failures.clone()))?;
+        registry.register(Box::new(github_api_calls.clone()))?;
+        registry.register(Box::new(github_rate_limit_remaining.clone()))?;
+        registry.register(Box::new(db_query_duration.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(open_threads.clone()))?;
+        registry.register(Box::new(unanswered_threads.clone()))?;
+
+        Ok(Self {
+            registry,
+            discord_events,
+            command_invocations,
+            command_failures,
+            github_api_calls,
+            github_rate_limit_remaining,
+            db_query_duration,
+            http_request_duration,
+            open_threads,
+            unanswered_threads,
+        })
+    }
+
+    /// Starts timing a database query. The latency is recorded when the timer is dropped.
+    pub fn time_query(&self, query: &str) -> HistogramTimer {
+        self.db_query_duration
+            .with_label_values(&[query])
+            .start_timer()
+    }
+
+    pub fn record_github_call<T, E>(&self, endpoint: &str, res: &Result<T, E>) {
+        let outcome = if res.is_ok() { "success" } else { "error" };
+        self.github_api_calls
+            .with_label_values(&[endpoint, outcome])
+            .inc();
+    }
+
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buf = String::new();
+        TextEncoder::new().encode_utf8(&self.registry.gather(), &mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    match state.db.metrics.render() {
+        Ok(res) => Ok(([(header::CONTENT_TYPE, TEXT_FORMAT)], res)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Records how long each request took, labelled with the route template rather than the raw path
+/// so that e.g. `/api/users/:id` doesn't create a new series per user.
+pub async fn track_http_requests<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let res = next.run(req).await;
+
+    state
+        .db
+        .metrics
+        .http_request_duration
+        .with_label_values(&[&method, &route, res.status().as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    res
+}
+
+/// Keeps the thread and GitHub rate limit gauges up to date.
+pub async fn update_gauges_periodically(db: DBQueries, crab: Github) {
+    loop {
+        match db.clone().get_issues_awaiting_response(None).await {
+            Ok(res) => {
+                db.metrics.open_threads.set(res.unresolved_issues);
+                db.metrics.unanswered_threads.set(res.unanswered_threads);
+            }
+            Err(e) => error!("Error when updating thread gauges: {e}"),
+        }
+
+        let rate_limit = match crab.client().await {
+            Ok(client) => client.ratelimit().get().await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        db.metrics.record_github_call("rate_limit", &rate_limit);
+        match rate_limit {
+            Ok(res) => db
+                .metrics
+                .github_rate_limit_remaining
+                .set(res.resources.core.remaining as i64),
+            Err(e) => error!("Error when getting the GitHub rate limit: {e}"),
+        }
+
+        sleep(Duration::from_secs(60)).await;
+    }
+}
@@ -1,10 +1,10 @@
 use axum::{
     extract::{Query, State},
-    http::{HeaderName, HeaderValue},
+    http::{HeaderName, HeaderValue, StatusCode},
     response::{IntoResponse, Redirect},
 };
 use axum_extra::extract::cookie::{Cookie, PrivateCookieJar};
-use chrono::{DateTime, Days, NaiveDateTime, Utc};
+use chrono::{Days, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use time::Duration;
@@ -33,7 +33,11 @@ pub async fn github_callback(
     State(state): State<AppState>,
     callback_code: Query<GithubToken>,
     jar: PrivateCookieJar,
-) -> impl IntoResponse {
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let Some(oauth) = state.oauth.clone() else {
+        return Err((StatusCode::NOT_FOUND, "GitHub OAuth hasn't been configured"));
+    };
+
     let code = Callback {
         code: callback_code.code.clone(),
     };
@@ -43,7 +47,7 @@ pub async fn github_callback(
     // paste callback code from JSON and send to access token URL to generate an access token
     let post = ctx
         .post("https://github.com/login/oauth/access_token")
-        .basic_auth(&state.oauth_id, Some(&state.oauth_secret))
+        .basic_auth(&oauth.client_id, Some(&oauth.client_secret))
         .json(&code)
         .header(
             HeaderName::from_lowercase(b"accept").unwrap(),
@@ -76,7 +80,7 @@ pub async fn github_callback(
 
     let cookie_string = format!("{}--{}", &user.login, json_response.access_token);
 
-    let cookie = Cookie::build("foo", cookie_string)
+    let cookie = Cookie::build("session_id", cookie_string)
         .secure(true)
         .max_age(Duration::DAY)
         .finish();
@@ -84,17 +88,15 @@ pub async fn github_callback(
     let user_session = UserSession {
         name: user.login,
         session_id: json_response.access_token,
-        expires_at: DateTime::<Utc>::from_utc(
-            NaiveDateTime::from_timestamp_opt(61, 0).unwrap(),
-            Utc,
-        ) + Days::new(1),
+        expires_at: Utc::now() + Days::new(1),
     };
 
     Persist::add_record(state.persist, user_session).unwrap();
 
-    (jar.add(cookie.clone()), Redirect::permanent("/dashboard"))
+    Ok((jar.add(cookie.clone()), Redirect::permanent("/dashboard")))
 }
 
+#[allow(dead_code)]
 #[derive(Serialize)]
 pub struct GithubUserResponse {
     name: String,
@@ -0,0 +1,115 @@
+use poise::serenity_prelude::User;
+
+use crate::{Context, Error};
+
+/// Staff tiers, lowest first. Each tier can do everything the tiers below it can. The derived
+/// `FromStr` and `Display` use the choice names; the database stores `as_str()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, poise::ChoiceParameter)]
+pub enum StaffTier {
+    #[name = "Helper (set severity, resolve threads)"]
+    Helper,
+    #[name = "Moderator (also lock, claim and assign threads)"]
+    Moderator,
+    #[name = "Maintainer (also elevate threads, refresh and change settings)"]
+    Maintainer,
+}
+
+impl StaffTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StaffTier::Helper => "helper",
+            StaffTier::Moderator => "moderator",
+            StaffTier::Maintainer => "maintainer",
+        }
+    }
+
+    /// Reads a tier as stored in the `staff_roles` table.
+    pub fn parse(tier: &str) -> Result<Self, String> {
+        match tier {
+            "helper" => Ok(Self::Helper),
+            "moderator" => Ok(Self::Moderator),
+            "maintainer" => Ok(Self::Maintainer),
+            _ => Err(format!(
+                "Unknown staff tier '{tier}' (expected helper, moderator or maintainer)"
+            )),
+        }
+    }
+}
+
+/// What staff commands need. Each capability is granted from a minimum tier upwards.
+#[derive(Clone, Copy, Debug)]
+pub enum Capability {
+    /// Set severities and resolve threads.
+    TriageThreads,
+    /// Lock, claim and assign threads, go on/off duty and view stats.
+    ModerateThreads,
+    /// Elevate threads to GitHub issues and backfill issues with `/refresh`.
+    ManageIssues,
+    /// Change settings, SLA targets, severity rules, recognition roles and staff roles.
+    ManageBot,
+}
+
+impl Capability {
+    pub fn min_tier(&self) -> StaffTier {
+        match self {
+            Capability::TriageThreads => StaffTier::Helper,
+            Capability::ModerateThreads => StaffTier::Moderator,
+            Capability::ManageIssues | Capability::ManageBot => StaffTier::Maintainer,
+        }
+    }
+}
+
+/// The user's staff tier in the guild the command was used in. Errors if the guild or the
+/// member can't be looked up, so that a failed lookup isn't mistaken for not being staff.
+pub async fn staff_tier(ctx: Context<'_>, user: &User) -> Result<Option<StaffTier>, Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(None);
+    };
+
+    let Some(guild) = ctx.data().guilds.get(guild_id).await else {
+        return Ok(None);
+    };
+
+    let member = match guild_id.member(ctx.serenity_context(), user.id).await {
+        Ok(res) => res,
+        Err(e) => return Err(format!("Couldn't look up {}'s roles: {e}", user.name).into()),
+    };
+
+    Ok(guild.tier_for(&member.roles))
+}
+
+/// Checks that the command's author has the capability, and tells them why not if they don't.
+async fn require(ctx: Context<'_>, capability: Capability) -> Result<bool, Error> {
+    let min_tier = capability.min_tier();
+
+    match staff_tier(ctx, ctx.author()).await? {
+        Some(tier) if tier >= min_tier => Ok(true),
+        _ => {
+            ctx.send(|m| {
+                m.content(format!(
+                    "You need to be a {} (or above) to use this command.",
+                    min_tier.as_str()
+                ))
+                .ephemeral(true)
+            })
+            .await?;
+            Ok(false)
+        }
+    }
+}
+
+pub async fn can_triage(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::TriageThreads).await
+}
+
+pub async fn can_moderate(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::ModerateThreads).await
+}
+
+pub async fn can_manage_issues(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::ManageIssues).await
+}
+
+pub async fn can_manage_bot(ctx: Context<'_>) -> Result<bool, Error> {
+    require(ctx, Capability::ManageBot).await
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use shuttle_persist::PersistInstance;
 
@@ -14,6 +14,27 @@ pub struct UserSession {
     pub expires_at: DateTime<Utc>,
 }
 
+impl UserSessions {
+    /// Adds a session, replacing the user's previous session if they had one.
+    fn upsert(&mut self, session: UserSession) {
+        match self
+            .user_sessions
+            .iter_mut()
+            .find(|user| user.name == session.name)
+        {
+            Some(user) => {
+                user.session_id = session.session_id;
+                user.expires_at = session.expires_at;
+            }
+            None => self.user_sessions.push(session),
+        }
+    }
+
+    fn remove_expired(&mut self, now: DateTime<Utc>) {
+        self.user_sessions.retain(|user| user.expires_at > now);
+    }
+}
+
 pub struct Persist;
 
 impl Persist {
@@ -26,21 +47,7 @@ impl Persist {
             }
         };
 
-        if instance
-            .user_sessions
-            .clone()
-            .iter()
-            .any(|user| user.name == session.name)
-        {
-            for mut i in instance.clone().user_sessions {
-                if i.name == session.name {
-                    i.session_id = session.session_id.clone();
-                    i.expires_at = session.expires_at;
-                }
-            }
-        } else {
-            instance.user_sessions.push(session);
-        }
+        instance.upsert(session);
 
         persist
             .save::<UserSessions>("usersessions", instance)
@@ -55,15 +62,7 @@ impl Persist {
             return Ok(());
         };
 
-        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp_opt(61, 0).unwrap(), Utc);
-
-        let new_instance = instance
-            .user_sessions
-            .into_iter()
-            .filter(|user| user.expires_at < dt)
-            .collect::<Vec<UserSession>>();
-
-        instance.user_sessions = new_instance;
+        instance.remove_expired(Utc::now());
 
         persist
             .save::<UserSessions>("usersessions", instance)
@@ -72,6 +71,7 @@ impl Persist {
         Ok(())
     }
 
+    #[allow(dead_code)]
     pub fn check_record_exists(
         persist: PersistInstance,
         split: String,
@@ -94,4 +94,64 @@ impl Persist {
             Ok(false)
         }
     }
+
+    /// Returns the GitHub login for a `login--token` session cookie, if the session exists.
+    pub fn session_user(persist: PersistInstance, cookie: &str) -> Option<String> {
+        let instance = persist.load::<UserSessions>("usersessions").ok()?;
+
+        let (name, session_id) = cookie.split_once("--")?;
+
+        instance
+            .user_sessions
+            .into_iter()
+            .find(|user| {
+                user.name == name && user.session_id == session_id && user.expires_at > Utc::now()
+            })
+            .map(|user| user.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn session(name: &str, session_id: &str, expires_at: DateTime<Utc>) -> UserSession {
+        UserSession {
+            name: name.to_string(),
+            session_id: session_id.to_string(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn upsert_replaces_the_users_previous_session() {
+        let now = Utc::now();
+        let mut sessions = UserSessions {
+            user_sessions: vec![session("alice", "old", now)],
+        };
+
+        sessions.upsert(session("alice", "new", now + Duration::days(1)));
+        sessions.upsert(session("bob", "token", now));
+
+        assert_eq!(sessions.user_sessions.len(), 2);
+        assert_eq!(sessions.user_sessions[0].session_id, "new");
+        assert_eq!(sessions.user_sessions[0].expires_at, now + Duration::days(1));
+    }
+
+    #[test]
+    fn remove_expired_keeps_sessions_that_havent_expired() {
+        let now = Utc::now();
+        let mut sessions = UserSessions {
+            user_sessions: vec![
+                session("alice", "token", now + Duration::hours(1)),
+                session("bob", "token", now - Duration::hours(1)),
+            ],
+        };
+
+        sessions.remove_expired(now);
+
+        assert_eq!(sessions.user_sessions.len(), 1);
+        assert_eq!(sessions.user_sessions[0].name, "alice");
+    }
 }
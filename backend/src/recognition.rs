@@ -0,0 +1,174 @@
+use crate::database::{ContributionCount, DBQueries, RecognitionRole};
+use crate::guilds::Guilds;
+use crate::settings::SharedSettings;
+use poise::serenity_prelude::{GuildId, Http, Member, RoleId, UserId};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+#[derive(Default)]
+pub struct RecognitionSummary {
+    pub granted: usize,
+    pub revoked: usize,
+    pub failed: usize,
+}
+
+/// Grants and revokes recognition roles in every guild on the interval set in the settings
+/// (hourly by default).
+pub async fn sync_recognition_roles_periodically(
+    http: Arc<Http>,
+    db: DBQueries,
+    guilds: Guilds,
+    settings: SharedSettings,
+) {
+    loop {
+        for guild_id in guilds.ids().await {
+            match sync_recognition_roles(&http, db.clone(), guild_id).await {
+                Ok(summary) => {
+                    if summary.granted > 0 || summary.revoked > 0 || summary.failed > 0 {
+                        info!(
+                            "Recognition roles synced in {guild_id}: {} granted, {} revoked, {} failed",
+                            summary.granted, summary.revoked, summary.failed
+                        );
+                    }
+                }
+                Err(e) => error!("Error while syncing recognition roles in {guild_id}: {e}"),
+            }
+        }
+
+        let interval_minutes = settings.read().await.recognition_sync_interval_minutes;
+        sleep(Duration::from_secs(interval_minutes.max(1) * 60)).await;
+    }
+}
+
+/// Grants each recognition role to members who meet its thresholds, and revokes it from members the bot
+/// previously granted it to who no longer do. Roles given out by hand are never revoked.
+pub async fn sync_recognition_roles(
+    http: &Arc<Http>,
+    db: DBQueries,
+    guild_id: GuildId,
+) -> Result<RecognitionSummary, anyhow::Error> {
+    let mut summary = RecognitionSummary::default();
+
+    let roles = db
+        .clone()
+        .get_recognition_roles(guild_id.to_string())
+        .await?;
+    if roles.is_empty() {
+        return Ok(summary);
+    }
+
+    let members = get_all_members(http, guild_id).await?;
+
+    for role in roles {
+        let role_id = RoleId(role.role_id.parse::<u64>()?);
+
+        let contributions = db
+            .clone()
+            .get_contribution_counts(guild_id.to_string(), role.window_days)
+            .await?
+            .into_iter()
+            .map(|count| (count.user_id.clone(), count))
+            .collect::<HashMap<String, ContributionCount>>();
+
+        let granted_by_bot = db
+            .clone()
+            .get_recognition_role_holders(role.role_id.clone())
+            .await?
+            .into_iter()
+            .collect::<HashSet<String>>();
+
+        for member in members.iter().filter(|member| !member.user.bot) {
+            let contribution = contributions.get(&member.user.id.to_string());
+            let qualifies = contribution.is_some_and(|count| qualifies_for(&role, count));
+            let has_role = member.roles.contains(&role_id);
+
+            let action = if qualifies && !has_role {
+                http.add_member_role(
+                    guild_id.0,
+                    member.user.id.0,
+                    role_id.0,
+                    Some("Reached recognition role threshold"),
+                )
+                .await
+                .map(|_| "granted")
+            } else if !qualifies && has_role && granted_by_bot.contains(&member.user.id.to_string()) {
+                http.remove_member_role(
+                    guild_id.0,
+                    member.user.id.0,
+                    role_id.0,
+                    Some("Dropped below recognition role threshold"),
+                )
+                .await
+                .map(|_| "revoked")
+            } else {
+                continue;
+            };
+
+            let action = match action {
+                Ok(res) => res,
+                Err(e) => {
+                    error!(
+                        "Couldn't update recognition role {role_id} for {}: {e}",
+                        member.user.name
+                    );
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+
+            match action {
+                "granted" => summary.granted += 1,
+                _ => summary.revoked += 1,
+            }
+
+            if let Err(e) = db
+                .clone()
+                .log_recognition_role_change(
+                    guild_id.to_string(),
+                    member.user.id.to_string(),
+                    member.user.name.clone(),
+                    role.role_id.clone(),
+                    action,
+                    contribution.map_or(0, |count| count.resolved_threads),
+                    contribution.map_or(0, |count| count.accepted_solutions),
+                )
+                .await
+            {
+                error!("Couldn't log recognition role change: {e}");
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn qualifies_for(role: &RecognitionRole, count: &ContributionCount) -> bool {
+    let resolved = role
+        .min_resolved_threads
+        .is_some_and(|min| count.resolved_threads >= min.into());
+    let accepted = role
+        .min_accepted_solutions
+        .is_some_and(|min| count.accepted_solutions >= min.into());
+
+    resolved || accepted
+}
+
+async fn get_all_members(http: &Arc<Http>, guild_id: GuildId) -> Result<Vec<Member>, anyhow::Error> {
+    let mut members = Vec::new();
+    let mut after: Option<UserId> = None;
+
+    loop {
+        let page = guild_id.members(http, Some(1000), after).await?;
+        let page_len = page.len();
+        after = page.last().map(|member| member.user.id);
+        members.extend(page);
+
+        if page_len < 1000 {
+            break;
+        }
+    }
+
+    Ok(members)
+}
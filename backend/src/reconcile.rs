@@ -0,0 +1,249 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use poise::serenity_prelude::{self as serenity, Channel, ChannelId, GuildChannel, Http};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+use crate::backfill::{archived_threads_page, next_archived_page, thread_record};
+use crate::database::{DBQueries, KnownThread, ThreadState, Upserted};
+use crate::guilds::{GuildConfig, Guilds};
+use crate::settings::SharedSettings;
+
+/// Threads the bot thinks are open but Discord doesn't list as active are looked up one at a time,
+/// so only this many are checked per guild each run. The rest are picked up on later runs.
+const MAX_THREAD_LOOKUPS: usize = 200;
+
+#[derive(Default)]
+pub struct ReconcileSummary {
+    pub created: usize,
+    pub filled: usize,
+    pub archived: usize,
+    pub reopened: usize,
+    pub deleted: usize,
+    pub failed: usize,
+    pub unchecked: usize,
+}
+
+impl ReconcileSummary {
+    fn is_empty(&self) -> bool {
+        self.created == 0
+            && self.filled == 0
+            && self.archived == 0
+            && self.reopened == 0
+            && self.deleted == 0
+            && self.failed == 0
+            && self.unchecked == 0
+    }
+}
+
+impl fmt::Display for ReconcileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} missing threads added, {} filled in, {} archived, {} reopened, {} deleted, {} failed, {} left to check",
+            self.created,
+            self.filled,
+            self.archived,
+            self.reopened,
+            self.deleted,
+            self.failed,
+            self.unchecked
+        )
+    }
+}
+
+/// Reconciles every guild on the interval set in the settings (hourly by default), starting
+/// straight away so anything missed while the bot was offline is caught on startup.
+pub async fn reconcile_periodically(
+    http: Arc<Http>,
+    db: DBQueries,
+    guilds: Guilds,
+    settings: SharedSettings,
+) {
+    loop {
+        for guild_id in guilds.ids().await {
+            let Some(guild) = guilds.get(guild_id).await else {
+                continue;
+            };
+
+            match reconcile_guild(&http, db.clone(), &guild).await {
+                Ok(summary) => {
+                    if !summary.is_empty() {
+                        info!(
+                            "Reconciled threads in {} ({guild_id}): {summary}",
+                            guild.name
+                        );
+                    }
+                }
+                Err(e) => error!("Error while reconciling threads in {guild_id}: {e}"),
+            }
+        }
+
+        let interval_minutes = settings.read().await.reconcile_interval_minutes;
+        sleep(Duration::from_secs(interval_minutes.max(1) * 60)).await;
+    }
+}
+
+/// Compares the guild's active threads, and threads archived within the lookback, with the
+/// `issues` table. Missing rows are created, missing posters and first responders are filled in,
+/// and threads the bot thinks are open but Discord doesn't are checked for being archived or
+/// deleted. Older threads are left to `/refresh`.
+pub async fn reconcile_guild(
+    http: &Arc<Http>,
+    db: DBQueries,
+    guild: &GuildConfig,
+) -> Result<ReconcileSummary, anyhow::Error> {
+    let mut summary = ReconcileSummary::default();
+
+    let known = db
+        .clone()
+        .reconcile_get_threads(guild.guild_id.to_string())
+        .await?
+        .into_iter()
+        .map(|thread| (thread.thread_id.clone(), thread))
+        .collect::<HashMap<String, KnownThread>>();
+
+    let mut threads = guild.guild_id.get_active_threads(http).await?.threads;
+    threads.retain(|thread| guild.watches(thread.parent_id));
+    for forum_id in &guild.help_forum_ids {
+        threads.extend(
+            recently_archived_threads(http, *forum_id, guild.settings.reconcile_lookback_days)
+                .await?,
+        );
+    }
+
+    let mut seen = HashSet::new();
+    for thread in &threads {
+        let thread_id = thread.id.to_string();
+        if !seen.insert(thread_id.clone()) {
+            continue;
+        }
+
+        let known_thread = known.get(&thread_id);
+        let read_messages = known_thread.is_none_or(|known_thread| !known_thread.complete);
+
+        let record = match thread_record(http, guild, thread, read_messages).await {
+            Ok(res) => res,
+            Err(e) => {
+                error!("Couldn't read the messages in {thread_id}: {e}");
+                summary.failed += 1;
+                continue;
+            }
+        };
+        let state = if record.archived {
+            ThreadState::Archived
+        } else {
+            ThreadState::Active
+        };
+
+        match db.clone().backfill_thread(record).await {
+            Ok(Upserted::Unchanged) => {}
+            Ok(Upserted::Created) => summary.created += 1,
+            Ok(Upserted::Updated) => {
+                let Some(known_thread) = known_thread else {
+                    continue;
+                };
+                if known_thread.thread_state.as_deref() != Some(state.as_str()) {
+                    match state {
+                        ThreadState::Archived => summary.archived += 1,
+                        _ => summary.reopened += 1,
+                    }
+                }
+                if !known_thread.complete {
+                    summary.filled += 1;
+                }
+            }
+            Err(e) => {
+                error!("Couldn't save thread {thread_id}: {e}");
+                summary.failed += 1;
+            }
+        }
+    }
+
+    // anything the bot thinks is open that wasn't listed was archived long ago or deleted
+    let unlisted = known
+        .values()
+        .filter(|thread| {
+            thread.thread_state.as_deref() == Some(ThreadState::Active.as_str())
+                && !seen.contains(&thread.thread_id)
+        })
+        .collect::<Vec<&KnownThread>>();
+    summary.unchecked = unlisted.len().saturating_sub(MAX_THREAD_LOOKUPS);
+
+    for thread in unlisted.into_iter().take(MAX_THREAD_LOOKUPS) {
+        let Ok(thread_id) = thread.thread_id.parse::<u64>() else {
+            continue;
+        };
+
+        let state = match ChannelId(thread_id).to_channel(http).await {
+            Ok(Channel::Guild(channel))
+                if channel
+                    .thread_metadata
+                    .is_some_and(|metadata| metadata.archived) =>
+            {
+                ThreadState::Archived
+            }
+            // still open, and only missing from the active list because it changed since
+            Ok(_) => continue,
+            Err(serenity::Error::Http(e))
+                if e.status_code().is_some_and(|code| code.as_u16() == 404) =>
+            {
+                ThreadState::Deleted
+            }
+            Err(e) => {
+                error!("Couldn't look up thread {thread_id}: {e}");
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        match db
+            .clone()
+            .set_thread_state(thread.thread_id.clone(), state)
+            .await
+        {
+            Ok(true) => match state {
+                ThreadState::Deleted => summary.deleted += 1,
+                _ => summary.archived += 1,
+            },
+            Ok(false) => {}
+            Err(e) => {
+                error!("Couldn't update thread {thread_id}: {e}");
+                summary.failed += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Threads in the forum archived within the last `lookback_days` days.
+async fn recently_archived_threads(
+    http: &Arc<Http>,
+    forum_id: ChannelId,
+    lookback_days: u64,
+) -> Result<Vec<GuildChannel>, anyhow::Error> {
+    let cutoff = Utc::now() - ChronoDuration::days(lookback_days as i64);
+    let mut threads = Vec::new();
+    let mut before = None;
+
+    loop {
+        let page = archived_threads_page(http, forum_id, before).await?;
+        before = next_archived_page(&page, Some(cutoff));
+
+        threads.extend(page.threads.into_iter().filter(|thread| {
+            thread
+                .thread_metadata
+                .and_then(|metadata| metadata.archive_timestamp)
+                .is_some_and(|timestamp| timestamp.unix_timestamp() >= cutoff.timestamp())
+        }));
+
+        if before.is_none() {
+            break;
+        }
+    }
+
+    Ok(threads)
+}
@@ -1,30 +1,47 @@
 use axum::{
-    extract::{FromRef, State},
-    http::{Request, StatusCode},
+    extract::{FromRef, Path, Query, State},
+    http::{header, Request, StatusCode},
     middleware::{self, Next},
     response::IntoResponse,
     routing::get,
     Json, Router,
 };
 use axum_extra::extract::cookie::{Key, PrivateCookieJar};
+use poise::serenity_prelude::{GuildId, ShardManager};
 use shuttle_persist::PersistInstance;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::{ServeDir, ServeFile};
 
-use crate::database::{DBQueries, DashboardData};
+use crate::database::{
+    DBQueries, DashboardData, FeedbackOverview, Granularity, IssueFilters, LeaderboardPeriod,
+    LeaderboardQuery, ResponseTimePercentileBreakdown, StatsGroupBy, StatsQuery,
+    WeeklyStatsParams,
+};
+use crate::config::OAuthCredentials;
+use crate::export::{self, ExportBody, ExportFormat};
+use crate::github::Github;
+use crate::guilds::{GuildConfig, Guilds};
+use crate::health;
+use crate::metrics::{metrics, track_http_requests};
 use crate::oauth::github_callback;
+use crate::settings::{Settings, SharedSettings};
 use crate::Persist;
-use octocrab::Octocrab;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub crab: Octocrab,
-    pub oauth_id: String,
-    pub oauth_secret: String,
+    pub crab: Github,
+    pub oauth: Option<OAuthCredentials>,
     pub key: Key,
     pub persist: PersistInstance,
     pub db: DBQueries,
+    pub shard_manager: Arc<Mutex<ShardManager>>,
+    pub settings: SharedSettings,
+    pub guilds: Guilds,
 }
 
 // this impl tells `SignedCookieJar` how to access the key from our state
@@ -34,78 +51,516 @@ impl FromRef<AppState> for Key {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn init_router(
     public: PathBuf,
     db: DBQueries,
-    oauth_id: String,
-    oauth_secret: String,
+    oauth: Option<OAuthCredentials>,
     persist: PersistInstance,
-    crab: Octocrab,
+    crab: Github,
+    shard_manager: Arc<Mutex<ShardManager>>,
+    settings: SharedSettings,
+    guilds: Guilds,
 ) -> Router {
     let cors = CorsLayer::new().allow_methods(Any).allow_origin(Any);
 
     let state = AppState {
         crab,
         db,
-        oauth_id,
-        oauth_secret,
+        oauth,
         key: Key::generate(),
         persist,
+        shard_manager,
+        settings,
+        guilds,
     };
 
     let api_router = Router::new()
         .route("/issues", get(get_issues))
-        .route("/dashboard", get(dashboard));
+        .route("/dashboard", get(dashboard))
+        .route("/guilds", get(get_guilds))
+        .route("/sla/breaching", get(get_sla_breaching_issues))
+        .route("/stats", get(get_stats))
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/users/:id", get(get_contributor))
+        .route("/recognition/changes", get(get_recognition_role_changes))
+        .route("/config", get(get_config))
+        .route("/jobs/:id", get(get_job))
+        .route("/export/issues.csv", get(export_issues_csv))
+        .route("/export/issues.jsonl", get(export_issues_jsonl))
+        .route("/export/weekly_stats.csv", get(export_weekly_stats_csv))
+        .route("/export/weekly_stats.jsonl", get(export_weekly_stats_jsonl));
         // .layer(middleware::from_fn_with_state(state.clone(), check_authed));
 
     Router::new()
         .nest("/api", api_router)
-        .route("/health", get(health))
+        .route("/health", get(health::live))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
         .route("/github/callback", get(github_callback))
+        .route("/metrics", get(metrics))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_http_requests,
+        ))
         .with_state(state)
         .nest_service(
             "/",
-            ServeDir::new(&public).not_found_service(ServeFile::new(public.join("/index.html"))),
+            ServeDir::new(&public).not_found_service(ServeFile::new(public.join("index.html"))),
         )
         .layer(cors)
 }
 
-async fn health() -> impl IntoResponse {
-    (StatusCode::OK, "It works!".to_string())
+/// Picks which guild's issues a request is about. Every API route that shows a guild's data needs one.
+#[derive(Deserialize)]
+struct GuildQuery {
+    guild: Option<String>,
+}
+
+#[derive(Serialize)]
+struct GuildSummary {
+    #[serde(rename(serialize = "guildId"))]
+    guild_id: String,
+    name: String,
+}
+
+/// The GitHub login of whoever is logged in, if anyone.
+fn session_user(state: &AppState, jar: &PrivateCookieJar) -> Option<String> {
+    let cookie = jar.get("session_id")?;
+
+    Persist::session_user(state.persist.clone(), cookie.value())
+}
+
+/// Checks that the logged in user is allowed to view the guild they asked for, and returns it.
+async fn authorize_guild(
+    state: &AppState,
+    jar: &PrivateCookieJar,
+    guild: Option<&str>,
+) -> Result<GuildConfig, (StatusCode, String)> {
+    let Some(github_login) = session_user(state, jar) else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Log in with GitHub to view a guild".to_string(),
+        ));
+    };
+
+    let Some(guild) = guild else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Pick a guild with ?guild=<server id>".to_string(),
+        ));
+    };
+
+    let Ok(guild_id) = guild.parse::<u64>() else {
+        return Err((StatusCode::BAD_REQUEST, format!("{guild} isn't a guild ID")));
+    };
+
+    match state.guilds.get(GuildId(guild_id)).await {
+        Some(guild) if guild.allows_dashboard_user(&github_login) => Ok(guild),
+        _ => Err((
+            StatusCode::FORBIDDEN,
+            "You don't have access to this guild".to_string(),
+        )),
+    }
+}
+
+/// Guilds the logged in user can switch between on the dashboard.
+async fn get_guilds(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let Some(github_login) = session_user(&state, &jar) else {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Log in with GitHub to view guilds".to_string(),
+        ));
+    };
+
+    let guilds = state
+        .guilds
+        .for_dashboard_user(&github_login)
+        .await
+        .into_iter()
+        .map(|guild| GuildSummary {
+            guild_id: guild.guild_id.to_string(),
+            name: guild.name,
+        })
+        .collect::<Vec<GuildSummary>>();
+
+    Ok((StatusCode::OK, Json(guilds)))
+}
+
+async fn get_issues(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(filters): Query<IssueFilters>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    authorize_guild(&state, &jar, filters.guild.as_deref()).await?;
+
+    match state.db.clone().get_all_issues(filters).await {
+        Ok(res) => Ok((StatusCode::OK, Json(res))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn get_sla_breaching_issues(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+
+    match state.db.get_sla_breaching_issues(query.guild).await {
+        Ok(res) => Ok((StatusCode::OK, Json(res))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn get_stats(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+
+    if query.from > query.to {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "from should be on or before to".to_string(),
+        ));
+    }
+
+    let granularity = query.granularity.unwrap_or(Granularity::Week);
+    let max_range_days = granularity.max_range_days();
+    if (query.to - query.from).num_days() > max_range_days {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "A {} series can cover at most {max_range_days} days",
+                granularity.as_str()
+            ),
+        ));
+    }
+
+    match state.db.get_stats_series(query).await {
+        Ok(res) => Ok((StatusCode::OK, Json(res))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn get_leaderboard(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(query): Query<LeaderboardQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let guild = authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+
+    let period = query.period.unwrap_or(LeaderboardPeriod::Month);
+    let limit = query.limit.unwrap_or(25).clamp(1, 100);
+
+    match state
+        .db
+        .get_leaderboard(guild.guild_id.to_string(), period, limit)
+        .await
+    {
+        Ok(res) => Ok((StatusCode::OK, Json(res))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn get_contributor(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Path(id): Path<String>,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let guild = authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+
+    if id.parse::<u64>().is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{id} isn't a Discord user ID"),
+        ));
+    }
+
+    match state
+        .db
+        .get_contributor_profile(guild.guild_id.to_string(), id)
+        .await
+    {
+        Ok(Some(res)) => Ok((StatusCode::OK, Json(res))),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            "This user hasn't taken part in any issues".to_string(),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
 }
 
-async fn get_issues(State(state): State<AppState>) -> Result<impl IntoResponse, impl IntoResponse> {
-    match state.db.clone().get_all_issues().await {
+async fn get_recognition_role_changes(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+
+    match state.db.get_recognition_role_changes(100, query.guild).await {
         Ok(res) => Ok((StatusCode::OK, Json(res))),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
 }
 
-async fn dashboard(State(state): State<AppState>) -> Result<impl IntoResponse, impl IntoResponse> {
-    let last_four_weeks_stats = match state.db.clone().get_last_four_weeks_stats().await {
+/// A background job's status and progress, for whoever can view its guild.
+async fn get_job(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let job = match state.db.clone().get_job(id).await {
+        Ok(Some(res)) => res,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, format!("There's no job {id}"))),
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    authorize_guild(&state, &jar, Some(&job.guild_id)).await?;
+
+    Ok((StatusCode::OK, Json(job)))
+}
+
+#[derive(Serialize)]
+struct ConfigView {
+    settings: Settings,
+    #[serde(rename(serialize = "githubRepo"))]
+    github_repo: String,
+    #[serde(rename(serialize = "githubForumRepos"))]
+    github_forum_repos: HashMap<String, String>,
+}
+
+/// Read-only view of a guild's settings, plus the repositories its threads are elevated to.
+async fn get_config(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let guild = authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+
+    let repos = &state.crab.repos;
+
+    let config = ConfigView {
+        settings: guild.settings,
+        github_repo: repos.repo_for(None, guild.github_repo.as_ref()).to_string(),
+        github_forum_repos: repos
+            .forum_repos
+            .iter()
+            .map(|(forum_id, repo)| (forum_id.to_string(), repo.to_string()))
+            .collect(),
+    };
+
+    Ok((StatusCode::OK, Json(config)))
+}
+
+async fn export_issues_csv(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(filters): Query<IssueFilters>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    authorize_guild(&state, &jar, filters.guild.as_deref()).await?;
+
+    let format = ExportFormat::Csv;
+    Ok(export_response("issues", format, export::export_issues(state.db, filters, format)))
+}
+
+async fn export_issues_jsonl(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(filters): Query<IssueFilters>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    authorize_guild(&state, &jar, filters.guild.as_deref()).await?;
+
+    let format = ExportFormat::JsonLines;
+    Ok(export_response("issues", format, export::export_issues(state.db, filters, format)))
+}
+
+async fn export_weekly_stats_csv(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(params): Query<WeeklyStatsParams>,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let format = ExportFormat::Csv;
+    authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+    let (weeks, timezone) = match validate_weekly_stats_params(&state.db, params).await {
+        Ok(res) => res,
+        Err(e) => return Err(e),
+    };
+    let body = export::export_weekly_stats(state.db, weeks, timezone, query.guild, format);
+
+    Ok(export_response("weekly_stats", format, body))
+}
+
+async fn export_weekly_stats_jsonl(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(params): Query<WeeklyStatsParams>,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    let format = ExportFormat::JsonLines;
+    authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+    let (weeks, timezone) = match validate_weekly_stats_params(&state.db, params).await {
+        Ok(res) => res,
+        Err(e) => return Err(e),
+    };
+    let body = export::export_weekly_stats(state.db, weeks, timezone, query.guild, format);
+
+    Ok(export_response("weekly_stats", format, body))
+}
+
+fn export_response(name: &str, format: ExportFormat, body: ExportBody) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}.{}\"", format.extension()),
+            ),
+        ],
+        body,
+    )
+}
+
+/// Checks the weekly stats params, filling in the defaults (the last 4 weeks, in UTC).
+async fn validate_weekly_stats_params(
+    db: &DBQueries,
+    params: WeeklyStatsParams,
+) -> Result<(i32, String), (StatusCode, String)> {
+    let weeks = params.weeks.unwrap_or(4);
+    if !(1..=52).contains(&weeks) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "weeks should be between 1 and 52".to_string(),
+        ));
+    }
+
+    let timezone = params.timezone.unwrap_or_else(|| "UTC".to_string());
+    match db.clone().timezone_exists(&timezone).await {
+        Ok(true) => Ok((weeks, timezone)),
+        Ok(false) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unknown timezone: {timezone}"),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+async fn dashboard(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(params): Query<WeeklyStatsParams>,
+    Query(query): Query<GuildQuery>,
+) -> Result<impl IntoResponse, impl IntoResponse> {
+    authorize_guild(&state, &jar, query.guild.as_deref()).await?;
+    let guild = query.guild;
+
+    let (weeks, timezone) = match validate_weekly_stats_params(&state.db, params).await {
+        Ok(res) => res,
+        Err(e) => return Err(e),
+    };
+
+    let weekly_stats = match state
+        .db
+        .clone()
+        .get_weekly_stats(weeks, timezone.clone(), guild.clone())
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let issues_awaiting_response = match state
+        .db
+        .clone()
+        .get_issues_awaiting_response(guild.clone())
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let issues_opened_last_week = match state
+        .db
+        .clone()
+        .get_issues_opened_last_7_days(guild.clone())
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let sla_compliance = match state
+        .db
+        .clone()
+        .get_sla_compliance_stats(weeks, timezone, guild.clone())
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let assignee_stats = match state.db.clone().get_assignee_stats(guild.clone()).await {
+        Ok(res) => res,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let by_severity = match state
+        .db
+        .clone()
+        .get_response_time_percentiles(StatsGroupBy::Severity, guild.clone())
+        .await
+    {
+        Ok(res) => res,
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let by_category = match state
+        .db
+        .clone()
+        .get_response_time_percentiles(StatsGroupBy::Category, guild.clone())
+        .await
+    {
         Ok(res) => res,
         Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
     };
 
-    let issues_awaiting_response = match state.db.clone().get_issues_awaiting_response().await {
+    let feedback_summary = match state.db.clone().get_feedback_summary(guild.clone()).await {
         Ok(res) => res,
         Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
     };
 
-    let issues_opened_last_week = match state.db.get_issues_opened_last_7_days().await {
+    let recent_comments = match state.db.clone().get_recent_feedback_comments(10, guild).await {
         Ok(res) => res,
         Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
     };
 
     let dashboard_data = DashboardData {
-        last_four_weeks_stats,
+        weekly_stats,
         issues_awaiting_response,
         issues_opened_last_week,
+        sla_compliance,
+        assignee_stats,
+        response_time_percentiles: ResponseTimePercentileBreakdown {
+            by_severity,
+            by_category,
+        },
+        feedback: FeedbackOverview {
+            summary: feedback_summary,
+            recent_comments,
+        },
     };
 
     Ok((StatusCode::OK, Json(dashboard_data)))
 }
 
+#[allow(dead_code)]
 async fn check_authed<B>(
     State(state): State<AppState>,
     jar: PrivateCookieJar,
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::database::{DBQueries, SettingOverride};
+
+pub type SharedSettings = Arc<RwLock<Settings>>;
+
+/// Non-secret bot behaviour. Every field has a default here, and can be overridden at runtime
+/// by a row in the `settings` table keyed by the field name.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Channel ID of the forum that help threads are created in, for guilds that haven't set their own forums.
+    pub help_forum_id: u64,
+    /// Prepended to the title of issues created by `/elevate`, and used by `/refresh` to tell them apart.
+    pub elevated_issue_prefix: String,
+    /// Posted when a thread is elevated. `{issue_url}` is replaced with the link to the new issue.
+    pub elevated_message: String,
+    /// Posted when a thread is auto-assigned. `{assignee}` is replaced with a mention of the assignee.
+    pub auto_assign_message: String,
+    /// Posted along with the feedback buttons when a thread is resolved.
+    pub resolved_message: String,
+    pub recognition_sync_interval_minutes: u64,
+    /// How often help threads are checked against the `issues` table for anything the bot missed.
+    pub reconcile_interval_minutes: u64,
+    /// How far back the reconciler looks through archived threads for ones the bot missed.
+    pub reconcile_lookback_days: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            help_forum_id: 1116377484296978452,
+            elevated_issue_prefix: "[AUTOGENERATED]".to_string(),
+            elevated_message: "This issue was successfully elevated to a GitHub issue! You can find the issue at: {issue_url}
+                \n---\n
+                The thread will now be locked to avoid needing to keep both communication channels synced with each other. 
+                \nIf you're not the original poster and you're having the same issue, please open a new Help thread or comment on the GitHub issue.
+                \n---\n
+                Thank you!".to_string(),
+            auto_assign_message: "{assignee} has been assigned to this thread.".to_string(),
+            resolved_message: "Looks like this issue's been resolved! This thread will now be locked.\nIf you opened this thread, let us know whether it helped you - you can also leave a rating and a comment.".to_string(),
+            recognition_sync_interval_minutes: 60,
+            reconcile_interval_minutes: 60,
+            reconcile_lookback_days: 7,
+        }
+    }
+}
+
+impl Settings {
+    /// Applies overrides on top of the defaults, failing if any of them don't match a setting or have the wrong type.
+    pub fn from_overrides(overrides: Vec<SettingOverride>) -> Result<Self, String> {
+        Self::default().with_overrides(
+            overrides
+                .into_iter()
+                .map(|setting| (setting.key, setting.value.0)),
+        )
+    }
+
+    /// Applies overrides on top of these settings. Used for guilds, whose overrides are layered
+    /// on top of the global settings.
+    pub fn with_overrides(
+        &self,
+        overrides: impl IntoIterator<Item = (String, Value)>,
+    ) -> Result<Self, String> {
+        let Value::Object(mut settings) = serde_json::to_value(self).map_err(|e| e.to_string())?
+        else {
+            return Err("Settings should serialize to an object".to_string());
+        };
+
+        for (key, value) in overrides {
+            if !settings.contains_key(&key) {
+                let keys = settings.keys().cloned().collect::<Vec<String>>().join(", ");
+                return Err(format!("{key} isn't a setting (expected one of: {keys})"));
+            }
+            settings.insert(key, value);
+        }
+
+        serde_json::from_value(Value::Object(settings)).map_err(|e| format!("Invalid setting: {e}"))
+    }
+
+    /// Reads the overrides from the database.
+    pub async fn load(db: DBQueries) -> Result<Self, String> {
+        Self::from_overrides(db.get_settings().await?)
+    }
+}
+
+/// Fills in `{name}` placeholders in a message template.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |message, (name, value)| {
+            message.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Values typed into `/config set` are read as JSON if possible, so numbers stay numbers,
+/// and as plain strings otherwise, so templates don't need to be quoted.
+pub fn parse_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn overrides(values: &[(&str, Value)]) -> Vec<(String, Value)> {
+        values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn with_overrides_replaces_only_the_given_settings() {
+        let settings = Settings::default()
+            .with_overrides(overrides(&[
+                ("recognition_sync_interval_minutes", json!(15)),
+                ("elevated_issue_prefix", json!("[HELP]")),
+            ]))
+            .unwrap();
+
+        assert_eq!(settings.recognition_sync_interval_minutes, 15);
+        assert_eq!(settings.elevated_issue_prefix, "[HELP]");
+        assert_eq!(settings.help_forum_id, Settings::default().help_forum_id);
+    }
+
+    #[test]
+    fn with_overrides_layers_on_top_of_the_current_settings() {
+        let global = Settings::default()
+            .with_overrides(overrides(&[("elevated_issue_prefix", json!("[HELP]"))]))
+            .unwrap();
+        let guild = global
+            .with_overrides(overrides(&[("recognition_sync_interval_minutes", json!(5))]))
+            .unwrap();
+
+        assert_eq!(guild.elevated_issue_prefix, "[HELP]");
+        assert_eq!(guild.recognition_sync_interval_minutes, 5);
+    }
+
+    #[test]
+    fn with_overrides_rejects_unknown_settings() {
+        let e = Settings::default()
+            .with_overrides(overrides(&[("not_a_setting", json!(1))]))
+            .err()
+            .unwrap();
+
+        assert!(e.starts_with("not_a_setting isn't a setting"), "{e}");
+    }
+
+    #[test]
+    fn with_overrides_rejects_the_wrong_type() {
+        for value in [json!("soon"), json!(-1), json!(null)] {
+            assert!(
+                Settings::default()
+                    .with_overrides(overrides(&[(
+                        "recognition_sync_interval_minutes",
+                        value.clone()
+                    )]))
+                    .is_err(),
+                "{value} shouldn't be accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_value_keeps_json_types() {
+        assert_eq!(parse_value("15"), json!(15));
+        assert_eq!(parse_value("\"15\""), json!("15"));
+        assert_eq!(parse_value("Thread resolved!"), json!("Thread resolved!"));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::database::SeverityRule;
+use regex::{Regex, RegexBuilder};
+use tracing::error;
+
+/// Tag and keyword rules used to suggest an initial severity for a new help thread.
+pub struct SeverityRules {
+    tags: Vec<(String, i16)>,
+    keywords: Vec<(Regex, i16)>,
+}
+
+impl SeverityRules {
+    pub fn new(rules: Vec<SeverityRule>) -> Self {
+        let mut tags = Vec::new();
+        let mut keywords = Vec::new();
+
+        for rule in rules {
+            match rule.kind.as_str() {
+                "tag" => tags.push((rule.pattern, rule.severity)),
+                "keyword" => match Self::build_regex(&rule.pattern) {
+                    Ok(regex) => keywords.push((regex, rule.severity)),
+                    Err(e) => error!("Skipping severity rule {} with invalid regex: {e}", rule.id),
+                },
+                kind => error!("Skipping severity rule {} with unknown kind: {kind}", rule.id),
+            }
+        }
+
+        Self { tags, keywords }
+    }
+
+    pub fn build_regex(pattern: &str) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern).case_insensitive(true).build()
+    }
+
+    /// Returns the most severe category (lowest number) matched by any of the given forum tag IDs
+    /// or by a keyword in the given text.
+    pub fn suggest(&self, tags: &[String], text: &str) -> Option<i16> {
+        let tag_matches = self
+            .tags
+            .iter()
+            .filter(|(tag, _)| tags.contains(tag))
+            .map(|(_, severity)| *severity);
+
+        let keyword_matches = self
+            .keywords
+            .iter()
+            .filter(|(regex, _)| regex.is_match(text))
+            .map(|(_, severity)| *severity);
+
+        tag_matches.chain(keyword_matches).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i32, kind: &str, pattern: &str, severity: i16) -> SeverityRule {
+        SeverityRule {
+            id,
+            kind: kind.to_string(),
+            pattern: pattern.to_string(),
+            severity,
+        }
+    }
+
+    fn rules() -> SeverityRules {
+        SeverityRules::new(vec![
+            rule(1, "tag", "1001", 3),
+            rule(2, "tag", "1002", 2),
+            rule(3, "keyword", r"\bdown\b", 1),
+            rule(4, "keyword", "deploy(ment)? failed", 2),
+        ])
+    }
+
+    #[test]
+    fn suggest_matches_nothing() {
+        assert_eq!(rules().suggest(&["9999".to_string()], "How do I add a secret?"), None);
+        assert_eq!(rules().suggest(&[], ""), None);
+    }
+
+    #[test]
+    fn suggest_matches_tags() {
+        assert_eq!(rules().suggest(&["1001".to_string()], "question"), Some(3));
+    }
+
+    #[test]
+    fn suggest_matches_keywords_case_insensitively() {
+        assert_eq!(rules().suggest(&[], "My Deployment FAILED again"), Some(2));
+        // whole words only, so "download" doesn't match `\bdown\b`
+        assert_eq!(rules().suggest(&[], "the download is slow"), None);
+    }
+
+    #[test]
+    fn suggest_picks_the_most_severe_match() {
+        let tags = ["1001".to_string(), "1002".to_string()];
+        assert_eq!(rules().suggest(&tags, "question"), Some(2));
+        assert_eq!(rules().suggest(&tags, "everything is down"), Some(1));
+    }
+
+    #[test]
+    fn new_skips_invalid_rules() {
+        let rules = SeverityRules::new(vec![
+            rule(1, "keyword", "(unclosed", 1),
+            rule(2, "emoji", "🔥", 1),
+            rule(3, "keyword", "crash", 4),
+        ]);
+
+        assert_eq!(rules.suggest(&[], "(unclosed 🔥 crash"), Some(4));
+    }
+}
@@ -8,7 +8,8 @@ interface Record {
 	severity: number,
 	firstResponder?: string,
 	resolvedBy?: string,
-	discordThreadLink: string,
+	assignedTo?: string,
+	discordThreadLink?: string,
 	githubLink?: string,
 	categories?: string[],
 	creationDate: string,
@@ -48,6 +49,7 @@ export default function Issues() {
 						<th className="px-5 py-2">Severity</th>
 						<th className="px-5 py-2">First Responder</th>
 						<th className="px-5 py-2">Resolved By</th>
+						<th className="px-5 py-2">Assigned To</th>
 						<th className="px-5 py-2">Discord Thread Link</th>
 						<th className="px-5 py-2">Github Link</th>
 						<th className="px-5 py-2">Categories</th>
@@ -70,7 +72,12 @@ export default function Issues() {
 							{item.resolvedBy ? item.resolvedBy : "Not resolved yet"}
 							</td>
 			<td className="px-5">
+							{item.assignedTo ? item.assignedTo : "Unassigned"}
+							</td>
+			<td className="px-5">
+							{item.discordThreadLink ? 
 							<Link href={item.discordThreadLink} target="_blank">Click</Link>
+							: "No thread"}
 							</td>
 			<td className="px-5">
 							{item.githubLink ? 
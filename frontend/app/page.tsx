@@ -6,12 +6,12 @@ import Link from 'next/link'
 import Chart from '@/components/Chart'
 
 interface DashboardData {
-  lastFourWeeksStats: LastFourWeeksStats[],
+  weeklyStats: WeeklyStats[],
   issuesAwaitingResponse: IssuesAwaitingResponse,
   issuesOpenedLastWeek: IssuesOpenedLastWeek[],
 }
 
-interface LastFourWeeksStats {
+interface WeeklyStats {
   dateRange: string,
   totalIssues: number,
   totalElevatedIssues: number,
@@ -20,7 +20,9 @@ interface LastFourWeeksStats {
   extendedThreads: number,
   averageResponseTime: string,
   bestSolver?: string,
-  bestFirstResponder?: string
+  bestSolverCount?: number,
+  bestFirstResponder?: string,
+  bestFirstResponderCount?: number
 }
 
 interface IssuesOpenedLastWeek {
@@ -28,6 +30,11 @@ interface IssuesOpenedLastWeek {
   totalIssuesPerDay: number,
 }
 
+interface Guild {
+  guildId: string,
+  name: string,
+}
+
 interface IssuesAwaitingResponse {
   unansweredThreads: number,
   unresolvedIssues: number,
@@ -37,15 +44,36 @@ interface IssuesAwaitingResponse {
 export default function Home() {
 
   const [data, setData] = React.useState<DashboardData>();
+  const [guilds, setGuilds] = React.useState<Guild[]>([]);
+  const [guild, setGuild] = React.useState<string>("");
+
+	// guilds the logged in user can view - this is empty if they're not logged in
+	React.useEffect(() => {
+		const fetch_guilds = async () => {
+		try {
+			let res = await fetch(`//${window.location.host}/api/guilds`, {
+					mode: "cors",
+					credentials: "include"
+				});
+			if (res.ok) {
+				setGuilds(await res.json())
+			}
+		} catch (e: any) {
+				console.log(e.message)
+			}
+		}
+		fetch_guilds()
+	}, [])
 
 	React.useEffect(() => {
 		const fetch_data = async () => {
 			
-	let fetch_url = `//${window.location.host}/api/dashboard`
+	let fetch_url = guild ? `//${window.location.host}/api/dashboard?guild=${guild}` : `//${window.location.host}/api/dashboard`
 
 		try {
 			let res = await fetch(fetch_url, {
-					mode: "cors"
+					mode: "cors",
+					credentials: "include"
 				});
 			let json = await res.json();
 			setData(json)
@@ -56,11 +84,22 @@ export default function Home() {
 			}
 		}
 		fetch_data()
-	}, [])
+	}, [guild])
   
   return (
   <div>
       <div className="grid grid-cols-1 grid-rows-auto gap-2">
+      {guilds.length > 0 && (
+      <div className="p-4 px-10 border border-2 rounded-md w-[100%] flex flex-row gap-4 items-center col-span-1">
+        <label htmlFor="guild">Server</label>
+        <select id="guild" className="border rounded-md p-1" value={guild} onChange={(e) => setGuild(e.target.value)}>
+          <option value="">All servers</option>
+          {guilds.map((item) => (
+            <option key={item.guildId} value={item.guildId}>{item.name}</option>
+          ))}
+        </select>
+      </div>
+      )}
       <div className="p-4 px-10 border border-2 rounded-md w-[100%] flex flex-col gap-4 col-span-1">
       <h2 className="text-left text-2xl">Currently Outstanding</h2>
         <div className="grid grid-cols-3 grid-rows-1 gap-4 text-sm">
@@ -94,15 +133,15 @@ export default function Home() {
               </tr>
             </thead>
             <tbody>
-              {data?.lastFourWeeksStats?.map((item) => (
+              {data?.weeklyStats?.map((item) => (
                 <tr key={item.dateRange}>
                   <td className="px-2">{item.dateRange}</td>
                   <td className="px-2">{item.totalIssues}</td>
                   <td className="px-2">{item.totalElevatedIssues}</td>
                   <td className="px-2">{item.totalResolvedIssues}</td>
                   <td className="px-2">{item.averageResponseTime}</td>
-                  <td className="px-2">{item.bestSolver ? item.bestSolver : "No issues solved yet"}</td>
-                  <td className="px-2">{item.bestFirstResponder ? item.bestFirstResponder : "No help threads responded to yet :("}</td>
+                  <td className="px-2">{item.bestSolver ? `${item.bestSolver} (${item.bestSolverCount})` : "No issues solved yet"}</td>
+                  <td className="px-2">{item.bestFirstResponder ? `${item.bestFirstResponder} (${item.bestFirstResponderCount})` : "No help threads responded to yet :("}</td>
                   </tr>
               ))}
             </tbody>